@@ -16,17 +16,26 @@ impl TreeType {
 }
 pub fn ast_generator(output_dir: &str) -> std::io::Result<()> {
     define_ast(
-        &output_dir,
+        output_dir,
         "Expr",
-        vec!["scanner"],
+        vec!["scanner", "stmt", "callable", "rc"],
         vec![
             "Binary     : Box<Expr> left, Token operator, Box<Expr> right",
-            "Call       : Box<Expr> callee,  Box<Vec<Expr>> arguments",
-            "Assign     : usize id, String name, Box<Expr> value",
+            "Call       : Box<Expr> callee, Token paren, Vec<Argument> arguments",
+            "Assign     : usize id, String name, usize line, Box<Expr> value",
+            "CompoundAssign : usize id, String name, Token operator, Box<Expr> value, bool postfix",
             "Grouping   : Box<Expr> expression",
+            "Conditional : Box<Expr> condition, Box<Expr> then_branch, Box<Expr> else_branch",
+            "List       : Vec<Expr> elements",
+            "Map        : Token brace, Vec<Expr> keys, Vec<Expr> values",
+            "Index      : Box<Expr> object, Token bracket, Box<Expr> index",
+            "Get        : Box<Expr> object, Token name",
+            "SetIndex   : Box<Expr> object, Token bracket, Box<Expr> index, Box<Expr> value",
+            "CompoundSetIndex : Box<Expr> object, Token bracket, Box<Expr> index, Token operator, Box<Expr> value, bool postfix",
             "Logical    : Box<Expr> left, Token operator, Box<Expr> right",
             "Unary      : Token operator, Box<Expr> right",
-            "Variable   : usize id, String name",
+            "Variable   : usize id, String name, usize line",
+            "Lambda     : Rc<Vec<Parameter>> parameters, Rc<Vec<Stmt>> body",
         ],
         Some(vec![
         "Number(f64)",
@@ -37,18 +46,24 @@ pub fn ast_generator(output_dir: &str) -> std::io::Result<()> {
 
     )?;
     define_ast(
-        &output_dir,
+        output_dir,
         "Stmt",
-        vec!["expr", "rc"],
+        vec!["expr", "scanner", "callable", "pattern", "rc"],
         vec![
             "Block      : Vec<Stmt> statements",
             "Expression : Expr expression",
             "If         : Expr condition, Box<Stmt> then_branch, Option<Box<Stmt>> else_branch",
-            "Function   : String name, Rc<Vec<String>> parameters, Rc<Vec<Stmt>> body",
+            "Function   : String name, Rc<Vec<Parameter>> parameters, Rc<Vec<Stmt>> body",
             "Print      : Expr expression",
             "Return     : Option<Expr> value",
             "Var        : String name, Option<Expr> initializer",
+            "Const      : String name, Expr initializer",
             "While      : Expr condition, Box<Stmt> body",
+            "ForIn      : Token keyword, String name, Expr iterable, Box<Stmt> body",
+            "Match      : Token keyword, Expr subject, Vec<MatchArm> arms",
+            "Import     : Token keyword, String path, Option<String> alias, Vec<String> names",
+            "Throw      : Token keyword, Expr value",
+            "Try        : Token keyword, Vec<Stmt> body, Option<String> catch_name, Option<Vec<Stmt>> catch_body, Option<Vec<Stmt>> finally_body",
         ],
         None,
     )?;
@@ -66,9 +81,9 @@ fn define_ast(
     let mut tree_types: Vec<TreeType> = Vec::new();
     for import in imports {
         if import.eq("rc") {
-            write!(file, "use std::rc::Rc;\n")?;
+            writeln!(file, "use std::rc::Rc;")?;
         }else{
-        write!(file, "use crate::{}::*;\n", import)?;
+        writeln!(file, "use crate::{}::*;", import)?;
         }
     }
     write!(file, "\n\n")?;
@@ -86,17 +101,17 @@ fn define_ast(
             .collect();
         tree_types.push(TreeType::new(base_name.to_string(), fields))
     }
-    write!(file, "#[derive(Debug, PartialEq)]\n")?;
-    write!(file, "pub enum {} {{\n", filename)?;
+    writeln!(file, "#[derive(Debug, PartialEq)]")?;
+    writeln!(file, "pub enum {} {{", filename)?;
     if let Some(literal) = literals{
     for lit in &literal {
-        write!(file, "\t{},\n",lit)?;
+        writeln!(file, "\t{},",lit)?;
     }
     }
     for t in &tree_types {
-        write!(file, "\t{}{{\n",t.base_name)?;
+        writeln!(file, "\t{}{{",t.base_name)?;
         for f in &t.fields {
-            write!(file, "\t {},\n", f)?;
+            writeln!(file, "\t {},", f)?;
         }
     write!(file, "\t}},\n\n")?;
     }
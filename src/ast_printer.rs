@@ -1,60 +1,250 @@
-use crate::{expr::*, error::RloxError};
+use crate::callable::*;
+use crate::expr::*;
 use crate::scanner::*;
+use crate::stmt::*;
 
+/// Renders syntax trees in parenthesized prefix form, so `1 + 2 * 3` reads `(+ 1 (* 2 3))`.
 pub struct AstPrinter {}
 
-impl ExprVisitor<String> for AstPrinter {
-    fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<String, RloxError> {
-        self.parenthesize(
-            String::from_utf8(expr.operator.lexeme.to_vec()).unwrap(),
-            vec![expr.left.as_ref(), expr.right.as_ref()],
-        )
+impl AstPrinter {
+    pub fn print(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Number(n) => n.to_string(),
+            Expr::String(s) => format!("{:?}", s),
+            Expr::Boolean(b) => b.to_string(),
+            Expr::Nil => "nil".to_string(),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                left,
+                operator,
+                right,
+            } => self.parenthesize(&operator.lexeme, &[left.as_ref(), right.as_ref()]),
+            Expr::Unary { operator, right } => {
+                self.parenthesize(&operator.lexeme, &[right.as_ref()])
+            }
+            Expr::Grouping { expression } => self.parenthesize("group", &[expression.as_ref()]),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => self.parenthesize(
+                "?:",
+                &[condition.as_ref(), then_branch.as_ref(), else_branch.as_ref()],
+            ),
+            Expr::Variable { name, .. } => name.clone(),
+            Expr::Assign { name, value, .. } => {
+                self.parenthesize(&format!("= {}", name), &[value.as_ref()])
+            }
+            Expr::CompoundAssign {
+                id: _,
+                name,
+                operator,
+                value,
+                postfix,
+            } => self.update(name.clone(), operator, value, *postfix),
+            Expr::CompoundSetIndex {
+                object,
+                bracket: _,
+                index,
+                operator,
+                value,
+                postfix,
+            } => {
+                let target = self.parenthesize("[]", &[object.as_ref(), index.as_ref()]);
+                self.update(target, operator, value, *postfix)
+            }
+            Expr::Call {
+                callee,
+                paren: _,
+                arguments,
+            } => {
+                let mut parts = vec![self.print(callee)];
+                for argument in arguments {
+                    let value = self.print(&argument.value);
+                    parts.push(match &argument.name {
+                        Some(name) => format!("{}: {}", name, value),
+                        None => value,
+                    });
+                }
+                format!("(call {})", parts.join(" "))
+            }
+            Expr::List { elements } => {
+                let elements: Vec<&Expr> = elements.iter().collect();
+                self.parenthesize("list", &elements)
+            }
+            Expr::Map {
+                brace: _,
+                keys,
+                values,
+            } => {
+                let entries: Vec<&Expr> = keys
+                    .iter()
+                    .zip(values)
+                    .flat_map(|(key, value)| [key, value])
+                    .collect();
+                self.parenthesize("map", &entries)
+            }
+            Expr::Index {
+                object,
+                bracket: _,
+                index,
+            } => self.parenthesize("[]", &[object.as_ref(), index.as_ref()]),
+            Expr::Get { object, name } => {
+                self.parenthesize(&format!(". {}", name.lexeme), &[object.as_ref()])
+            }
+            Expr::SetIndex {
+                object,
+                bracket: _,
+                index,
+                value,
+            } => self.parenthesize("[]=", &[object.as_ref(), index.as_ref(), value.as_ref()]),
+            Expr::Lambda { parameters, body } => {
+                format!("(fun ({}) {})", self.parameters(parameters), self.statements(body))
+            }
+        }
     }
 
-    fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<String, RloxError>{
-        self.parenthesize("grouping".to_string(), vec![&expr.expression])
+    pub fn print_stmt(&self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Block { statements } => format!("(block {})", self.statements(statements)),
+            Stmt::Expression { expression } => format!("(; {})", self.print(expression)),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => match else_branch {
+                Some(else_branch) => format!(
+                    "(if {} {} {})",
+                    self.print(condition),
+                    self.print_stmt(then_branch),
+                    self.print_stmt(else_branch)
+                ),
+                None => format!("(if {} {})", self.print(condition), self.print_stmt(then_branch)),
+            },
+            Stmt::Function {
+                name,
+                parameters,
+                body,
+            } => format!(
+                "(fun {} ({}) {})",
+                name,
+                self.parameters(parameters),
+                self.statements(body)
+            ),
+            Stmt::Print { expression } => format!("(print {})", self.print(expression)),
+            Stmt::Return { value } => match value {
+                Some(value) => format!("(return {})", self.print(value)),
+                None => "(return)".to_string(),
+            },
+            Stmt::Var { name, initializer } => match initializer {
+                Some(initializer) => format!("(var {} {})", name, self.print(initializer)),
+                None => format!("(var {})", name),
+            },
+            Stmt::Const { name, initializer } => {
+                format!("(const {} {})", name, self.print(initializer))
+            }
+            Stmt::While { condition, body } => {
+                format!("(while {} {})", self.print(condition), self.print_stmt(body))
+            }
+            Stmt::Match {
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                let mut output = format!("(match {}", self.print(subject));
+                for arm in arms {
+                    output.push_str(&format!(" ({}", arm.pattern));
+                    if let Some(guard) = &arm.guard {
+                        output.push_str(&format!(" if {}", self.print(guard)));
+                    }
+                    output.push_str(&format!(" {})", self.print_stmt(&arm.body)));
+                }
+                output.push(')');
+                output
+            }
+            Stmt::Import {
+                keyword: _,
+                path,
+                alias,
+                names,
+            } => match alias {
+                Some(alias) => format!("(import {:?} as {})", path, alias),
+                None => format!("(from {:?} import {})", path, names.join(" ")),
+            },
+            Stmt::Throw { keyword: _, value } => format!("(throw {})", self.print(value)),
+            Stmt::Try {
+                keyword: _,
+                body,
+                catch_name,
+                catch_body,
+                finally_body,
+            } => {
+                let mut output = format!("(try ({})", self.statements(body));
+                if let (Some(name), Some(handler)) = (catch_name, catch_body) {
+                    output.push_str(&format!(" (catch {} {})", name, self.statements(handler)));
+                }
+                if let Some(finally_body) = finally_body {
+                    output.push_str(&format!(" (finally {})", self.statements(finally_body)));
+                }
+                output.push(')');
+                output
+            }
+            Stmt::ForIn {
+                keyword: _,
+                name,
+                iterable,
+                body,
+            } => format!(
+                "(for {} {} {})",
+                name,
+                self.print(iterable),
+                self.print_stmt(body)
+            ),
+        }
     }
 
-    fn visit_literal_expr(&self, expr: &LiteralExpr) -> Result<String,RloxError> {
-        if let Some(value) = &expr.value {
-            match value {
-                Literal::Identifier(val) => Ok( val.to_string() ),
-                Literal::Str(val) => Ok(val.to_string()),
-                Literal::Number(val) => Ok(val.to_string()),
-                Literal::True => Ok("true".to_string()),
-                Literal::False => Ok("false".to_string()),
-                Literal::Nil => Ok("nil".to_string())
-            }
-        } else {
-            Ok("nil".to_string())
+    fn parenthesize(&self, name: &str, expressions: &[&Expr]) -> String {
+        let mut output = format!("({}", name);
+        for expr in expressions {
+            output.push(' ');
+            output.push_str(&self.print(expr));
         }
+        output.push(')');
+        output
     }
 
-    fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<String,RloxError> {
-        self.parenthesize(
-            String::from_utf8(expr.operator.lexeme.to_vec()).unwrap(),
-            vec![expr.right.as_ref()],
-        )
+    /// `(+= x 2)`, or `(++ x)` and `(post++ x)` for increments and decrements.
+    fn update(&self, target: String, operator: &Token, value: &Expr, postfix: bool) -> String {
+        match operator.token_type {
+            TokenType::PlusPlus | TokenType::MinusMinus if postfix => {
+                format!("(post{} {})", operator.lexeme, target)
+            }
+            TokenType::PlusPlus | TokenType::MinusMinus => format!("({} {})", operator.lexeme, target),
+            _ => format!("({} {} {})", operator.lexeme, target, self.print(value)),
+        }
     }
 
-    fn visit_variable_expr(&self, variable: &VariableExpr) -> Result<String, RloxError> {
-        todo!()
-    }
-}
-impl AstPrinter {
-    pub fn print(&self, expr: &Expr) -> Result<String,RloxError> {
-        expr.accept(self)
+    fn statements(&self, statements: &[Stmt]) -> String {
+        statements
+            .iter()
+            .map(|stmt| self.print_stmt(stmt))
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 
-    fn parenthesize(&self, name: String, expressions: Vec<&Expr>) -> Result<String,RloxError> {
-        let mut output = String::new();
-        output.push_str(&format!("({}", name));
-        for expr in expressions {
-            let expression = expr.accept(self)?;
-            output.push_str(" ");
-            output.push_str(&expression);
-        }
-            output.push_str(")");
-        Ok(output)
+    fn parameters(&self, parameters: &[Parameter]) -> String {
+        parameters
+            .iter()
+            .map(|parameter| match (&parameter.default, parameter.rest) {
+                (_, true) => format!("...{}", parameter.name),
+                (Some(default), false) => format!("{} = {}", parameter.name, self.print(default)),
+                (None, false) => parameter.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 }
@@ -0,0 +1,57 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// How much work scripts may do before the interpreter stops them. Every limit is off
+/// by default.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    /// Statements executed plus expressions evaluated.
+    pub max_steps: Option<u64>,
+    pub deadline: Option<Instant>,
+    /// Lists, maps, strings and functions created.
+    pub max_allocations: Option<u64>,
+}
+
+/// Stops the scripts of an interpreter from any thread, as soon as they take their next step.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Lets scripts run again after a cancellation, as the prompt does before each entry.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Why a script was stopped before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps(u64),
+    Deadline,
+    Allocations(u64),
+    Cancelled,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Steps(max) => write!(f, "the script ran for more than {} steps", max),
+            Limit::Deadline => write!(f, "the script ran past its deadline"),
+            Limit::Allocations(max) => write!(f, "the script created more than {} values", max),
+            Limit::Cancelled => write!(f, "the script was cancelled"),
+        }
+    }
+}
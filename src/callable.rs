@@ -5,60 +5,290 @@ use std::time::UNIX_EPOCH;
 
 use crate::environment::*;
 use crate::error::*;
+use crate::expr::*;
 use crate::interpreter::*;
+use crate::module::*;
 use crate::stmt::*;
 
+/// One entry of a function's parameter list: `a`, `b = 2` or `...rest`.
+#[derive(Debug, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    /// Evaluated at call time, in the new call environment, when no argument is given.
+    pub default: Option<Expr>,
+    /// Collects the remaining positional arguments into a list. Only the last one can be.
+    pub rest: bool,
+}
+
+/// One argument at a call site, either positional `f(1)` or named `f(a: 1)`.
+#[derive(Debug, PartialEq)]
+pub struct Argument {
+    pub name: Option<String>,
+    pub value: Expr,
+}
+
+/// The number of positional arguments a callable accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    /// None when a rest parameter takes any number of extra arguments.
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exact(count: usize) -> Self {
+        Self {
+            min: count,
+            max: Some(count),
+        }
+    }
+
+    pub fn range(min: usize, max: usize) -> Self {
+        Self { min, max: Some(max) }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+}
+
+impl std::fmt::Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RloxFunction {
-    parameters: Rc<Vec<String>>,
+    /// None for lambdas.
+    name: Option<String>,
+    parameters: Rc<Vec<Parameter>>,
     body: Rc<Vec<Stmt>>,
     closure: Rc<RefCell<Environment>>,
+    /// The top level the function was defined in, so its globals are the ones of its own module.
+    scope: ModuleScope,
+}
+
+/// Whether a callable was written in Lox or is built into the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallableKind {
+    Function,
+    Native,
 }
 
 pub trait RloxCallable {
     fn call(&self, interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError>;
-    fn arity(&self) -> usize;
+    fn arity(&self) -> Arity;
+
+    /// The name it was declared with, if any.
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn kind(&self) -> CallableKind {
+        CallableKind::Native
+    }
+
+    /// Calls with named arguments following the positional ones. Callables without
+    /// parameter names reject any named argument.
+    fn call_named(
+        &self,
+        interpreter: &mut Interpreter,
+        args: &[Rc<Value>],
+        named: &[(String, Rc<Value>)],
+    ) -> Result<Rc<Value>, RloxError> {
+        match named.first() {
+            Some((name, _)) => Err(RloxError::RuntimeError {
+                lexeme: name.clone(),
+                message: "This function takes no named arguments.".to_string(),
+            }),
+            None => self.call(interpreter, args),
+        }
+    }
+}
+
+/// `<fn fib>` or `<native fn clock>`, leaving out the name of lambdas.
+impl std::fmt::Display for dyn RloxCallable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind() {
+            CallableKind::Function => "fn",
+            CallableKind::Native => "native fn",
+        };
+        match self.name() {
+            Some(name) => write!(f, "<{} {}>", kind, name),
+            None => write!(f, "<{}>", kind),
+        }
+    }
 }
 
 impl std::fmt::Debug for dyn RloxCallable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<lox fn>")
+        write!(f, "{}", self)
     }
 }
 
 impl RloxFunction {
     pub fn new(
-        parameters: Rc<Vec<String>>,
+        name: Option<String>,
+        parameters: Rc<Vec<Parameter>>,
         body: Rc<Vec<Stmt>>,
         closure: Rc<RefCell<Environment>>,
+        scope: ModuleScope,
     ) -> Self {
         Self {
+            name,
             parameters,
             body,
             closure,
+            scope,
         }
     }
 }
 
+impl RloxFunction {
+    /// Defines every parameter in `environment`: positional arguments first, then named
+    /// ones, then defaults for whatever is still missing. Extra positional arguments go
+    /// to the rest parameter; the caller has already checked there aren't too many.
+    fn bind(
+        &self,
+        interpreter: &mut Interpreter,
+        environment: &Rc<RefCell<Environment>>,
+        args: &[Rc<Value>],
+        named: &[(String, Rc<Value>)],
+    ) -> Result<(), RloxError> {
+        let (fixed, rest) = match self.parameters.split_last() {
+            Some((last, fixed)) if last.rest => (fixed, Some(last)),
+            _ => (self.parameters.as_slice(), None),
+        };
+
+        let mut bound: Vec<Option<Rc<Value>>> = vec![None; fixed.len()];
+        for (slot, arg) in bound.iter_mut().zip(args) {
+            *slot = Some(arg.clone());
+        }
+        for (name, value) in named {
+            let Some(index) = fixed.iter().position(|p| &p.name == name) else {
+                return Err(RloxError::RuntimeError {
+                    lexeme: name.clone(),
+                    message: format!("There is no parameter named '{}'.", name),
+                });
+            };
+            if bound[index].is_some() {
+                return Err(RloxError::RuntimeError {
+                    lexeme: name.clone(),
+                    message: format!("Parameter '{}' was given more than one value.", name),
+                });
+            }
+            bound[index] = Some(value.clone());
+        }
+
+        for (parameter, value) in fixed.iter().zip(bound) {
+            let value = match (value, &parameter.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => interpreter.evaluate_in(default, Rc::clone(environment))?,
+                (None, None) => {
+                    return Err(RloxError::RuntimeError {
+                        lexeme: parameter.name.clone(),
+                        message: format!("Missing argument for parameter '{}'.", parameter.name),
+                    })
+                }
+            };
+            environment.borrow().define(&parameter.name, value);
+        }
+        if let Some(rest) = rest {
+            let extra = args.get(fixed.len()..).unwrap_or_default().to_vec();
+            let extra = interpreter.allocate(Value::new_list(extra))?;
+            environment.borrow().define(&rest.name, extra);
+        }
+        Ok(())
+    }
+}
+
 impl RloxCallable for RloxFunction {
     fn call(&self, interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
-        let environment = Environment::new(Rc::clone(&self.closure));
+        self.call_named(interpreter, args, &[])
+    }
 
-        for (token, val) in self.parameters.iter().zip(args.iter()) {
-            environment.define(&token.to_string(), val.clone())
+    fn arity(&self) -> Arity {
+        let rest = self.parameters.last().is_some_and(|p| p.rest);
+        let fixed = self.parameters.len() - rest as usize;
+        Arity {
+            min: self
+                .parameters
+                .iter()
+                .take_while(|p| p.default.is_none() && !p.rest)
+                .count(),
+            max: if rest { None } else { Some(fixed) },
         }
-        if let Err(err) = interpreter.execute_block(&self.body, Rc::new(RefCell::new(environment))) {
-            match err {
-                RloxError::Return(val) => Ok(Rc::new(val)),
-                e => Err(e),
-            }
-        } else {
-            Ok(Rc::new(Value::Nil))
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn kind(&self) -> CallableKind {
+        CallableKind::Function
+    }
+
+    fn call_named(
+        &self,
+        interpreter: &mut Interpreter,
+        args: &[Rc<Value>],
+        named: &[(String, Rc<Value>)],
+    ) -> Result<Rc<Value>, RloxError> {
+        let environment = Rc::new(RefCell::new(Environment::new(Rc::clone(&self.closure))));
+        let caller = interpreter.enter_scope(self.scope.clone());
+        let result = self
+            .bind(interpreter, &environment, args, named)
+            .and_then(|_| interpreter.execute_block(&self.body, environment));
+        interpreter.enter_scope(caller);
+        match result {
+            Ok(()) => Ok(Rc::new(Value::Nil)),
+            Err(RloxError::Return(val)) => Ok(Rc::new(val)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub type NativeFn = fn(&mut Interpreter, &[Rc<Value>]) -> Result<Rc<Value>, RloxError>;
+
+/// A builtin implemented by a plain Rust function.
+#[derive(Debug, Clone)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub function: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn new(name: &'static str, arity: usize, function: NativeFn) -> Self {
+        Self::with_arity(name, Arity::exact(arity), function)
+    }
+
+    /// A native taking optional trailing arguments, which it finds missing from its slice.
+    pub fn with_arity(name: &'static str, arity: Arity, function: NativeFn) -> Self {
+        Self {
+            name,
+            arity,
+            function,
         }
     }
+}
+
+impl RloxCallable for NativeFunction {
+    fn call(&self, interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+        (self.function)(interpreter, args)
+    }
 
-    fn arity(&self) -> usize {
-        self.parameters.len()
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(self.name)
     }
 }
 
@@ -73,11 +303,16 @@ impl RloxCallable for Clock {
         Ok(Rc::new(Value::Number(since_the_epoch.as_millis() as f64)))
     }
 
-    fn arity(&self) -> usize {
-        0
+    fn arity(&self) -> Arity {
+        Arity::exact(0)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some("clock")
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct RloxClass {
     pub name: String,
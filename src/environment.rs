@@ -1,6 +1,6 @@
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     rc::Rc,
 };
 
@@ -10,6 +10,9 @@ use crate::{error::*, interpreter::*};
 pub struct Environment {
     pub enclosing: Option<Rc<RefCell<Environment>>>,
     pub values: RefCell<HashMap<String, Rc<Value>>>,
+    /// Names bound with `const`. The resolver rejects assignments to local constants,
+    /// so this only matters for globals.
+    constants: RefCell<HashSet<String>>,
 }
 
 impl Default for Environment {
@@ -17,6 +20,7 @@ impl Default for Environment {
         Self {
             enclosing: None,
             values: RefCell::new(HashMap::new()),
+            constants: RefCell::new(HashSet::new()),
         }
     }
 }
@@ -26,13 +30,25 @@ impl Environment {
         Self {
             enclosing: Some(enclosing),
             values: RefCell::new(HashMap::new()),
+            constants: RefCell::new(HashSet::new()),
         }
     }
+    /// Binds a mutable variable, replacing any earlier binding of the name, constant or not.
     pub fn define(&self, name: &str, value: Rc<Value>) {
+        self.constants.borrow_mut().remove(name);
         self.values
             .borrow_mut()
             .insert(name.to_string(), value);
     }
+    pub fn define_const(&self, name: &str, value: Rc<Value>) {
+        self.values
+            .borrow_mut()
+            .insert(name.to_string(), value);
+        self.constants.borrow_mut().insert(name.to_string());
+    }
+    pub fn names(&self) -> Vec<String> {
+        self.values.borrow().keys().cloned().collect()
+    }
     pub fn get_at(&self, distance: usize, token: &str) -> Result<Rc<Value>, RloxError> {
         if 0 ==distance {
             {
@@ -68,6 +84,12 @@ impl Environment {
     ) -> Result<(), RloxError> {
 
         if 0.eq(distance) {
+            if self.constants.borrow().contains(token) {
+                return Err(RloxError::RuntimeError {
+                    lexeme: token.to_string(),
+                    message: format!("Can't assign to constant '{}'.", token),
+                });
+            }
             self.values.borrow_mut().insert(token.to_string(), value.clone());
             Ok(())
         } else {
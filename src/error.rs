@@ -1,16 +1,84 @@
-use crate::{scanner::*, interpreter::{Value, Interpreter}};
+use std::rc::Rc;
+
+use crate::{budget::Limit, map::RloxMap, scanner::*, interpreter::{Value, Interpreter}};
 
 #[derive(Debug)]
 pub enum RloxError {
     ScanError { character: char, message: String },
     UnterminatedStringError { token: String, message: String },
     ParseError { current: usize, token: Token, message: String},
+    ResolveError { name: String, message: String },
     RuntimeError { lexeme: String, message: String},
     Return(Value),
-    InterpreterError,
+    /// A value on its way to the nearest `catch`, raised by `throw` on `line` or made from
+    /// a runtime error by `located`, with the calls that were in progress at the time.
+    Thrown { value: Rc<Value>, line: usize, trace: Vec<CallFrame> },
+    /// The script asked to stop with this exit code by calling `exit`.
+    Exit(i32),
+    /// The interpreter's budget ran out, or its script was cancelled. No `catch` stops it.
+    Exhausted(Limit),
+}
+
+/// A call in progress: the function called and the line it was called on.
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    pub function: String,
+    pub line: usize,
+}
+
+/// How many of the innermost and of the outermost frames a long trace shows.
+const TRACE_ENDS: usize = 10;
+
+/// Distinguishes errors caught before a program runs from errors raised while it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The source failed to scan, parse or resolve.
+    Compile,
+    /// The program failed while executing.
+    Runtime,
 }
 
 impl RloxError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            RloxError::ScanError { .. }
+            | RloxError::UnterminatedStringError { .. }
+            | RloxError::ParseError { .. }
+            | RloxError::ResolveError { .. } => ErrorCategory::Compile,
+            RloxError::RuntimeError { .. }
+            | RloxError::Return(_)
+            | RloxError::Thrown { .. }
+            | RloxError::Exit(_)
+            | RloxError::Exhausted(_) => ErrorCategory::Runtime,
+        }
+    }
+
+    /// Turns a runtime error into a catchable error value, a map holding its `message`,
+    /// the `token` it happened at and the `line` of the expression that raised it.
+    /// `trace` holds the calls in progress, outermost first.
+    /// Other errors, including ones already located, are returned unchanged.
+    pub fn located(self, line: usize, trace: &[CallFrame]) -> RloxError {
+        let RloxError::RuntimeError { lexeme, message } = self else {
+            return self;
+        };
+        let mut error = RloxMap::default();
+        let fields = [
+            ("message", Value::Str(message)),
+            ("token", Value::Str(lexeme)),
+            ("line", Value::Number(line as f64)),
+        ];
+        for (key, value) in fields {
+            error
+                .insert(Rc::new(Value::Str(key.to_string())), Rc::new(value), key)
+                .expect("string keys are always hashable");
+        }
+        RloxError::Thrown {
+            value: Rc::new(Value::new_map(error)),
+            line,
+            trace: trace.to_vec(),
+        }
+    }
+
     pub fn report(&self){
         match &self {
             RloxError::ScanError { character, message } => {
@@ -19,14 +87,53 @@ impl RloxError {
             RloxError::ParseError {current, token, message} => {
                 eprintln!("[position {}] Error {} lexeme {:?}",current,message, token.lexeme )
             }
-            RloxError::InterpreterError => eprintln!("todo: implement interpreter error messages"),
+            RloxError::ResolveError { name, message } => {
+                eprintln!("[name {}] Error {}", name, message)
+            }
             RloxError::UnterminatedStringError { token, message } => {
                eprintln!("[line {}] Error {}", token, message)
             }
             RloxError::RuntimeError { lexeme, message } =>
                eprintln!("[token {}] Error {}", lexeme, message),
             RloxError::Return(a) => eprintln!("{}", Interpreter::stringify(a)),
+            RloxError::Thrown { value, line, trace } => {
+                match error_fields(value) {
+                    Some((message, token)) => {
+                        eprintln!("[line {}] Error at '{}': {}", line, token, message)
+                    }
+                    None => eprintln!("[line {}] Uncaught {}", line, value.repr()),
+                }
+                // a runaway recursion leaves a thousand frames; show both ends of it
+                let hidden = trace.len().saturating_sub(2 * TRACE_ENDS);
+                for (i, frame) in trace.iter().rev().enumerate() {
+                    if i == TRACE_ENDS && hidden > 0 {
+                        eprintln!("    ... {} more calls", hidden);
+                    }
+                    if i >= TRACE_ENDS && i < TRACE_ENDS + hidden {
+                        continue;
+                    }
+                    eprintln!("    in {}, called on line {}", frame.function, frame.line);
+                }
+            }
+            RloxError::Exit(_) => {}
+            RloxError::Exhausted(limit) => eprintln!("Stopped: {}.", limit),
 
         }
     }
 }
+
+/// The message and token of an error value made by `RloxError::located`.
+fn error_fields(value: &Value) -> Option<(String, String)> {
+    let Value::Map(map) = value else {
+        return None;
+    };
+    let map = map.borrow();
+    let field = |key: &str| match map.get(&Value::Str(key.to_string()), key) {
+        Ok(Some(value)) => match value.as_ref() {
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    Some((field("message")?, field("token")?))
+}
@@ -1,20 +1,73 @@
+use crate::budget::*;
 use crate::callable::*;
 use crate::environment::*;
-use crate::error::RloxError;
+use crate::error::{CallFrame, RloxError};
+use crate::io::*;
+use crate::json::*;
 use crate::expr::Expr;
+use crate::map::*;
+use crate::math::*;
+use crate::module::*;
+use crate::natives::*;
+use crate::parser::*;
+use crate::pattern::*;
+use crate::resolver::*;
 use crate::scanner::*;
 use crate::stmt::*;
+use crate::strings::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
+use std::time::Instant;
+
+/// How deep calls may nest before a script gets a `Stack overflow` error.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// Calls and nested values are followed on a fresh stack segment of `STACK_SEGMENT` bytes
+/// once less than this is left, so deep recursion never overflows the thread's stack.
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// How deep lists and maps are followed when printing or comparing them.
+pub const MAX_VALUE_DEPTH: usize = 1000;
+
+/// Steps between looks at the clock for `Budget::deadline`, which costs more than a step.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// Runs one level of a recursion that follows nested values or calls, on a fresh stack
+/// segment when the current one is running out.
+fn nested<T>(run: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, run)
+}
 
 #[derive(Debug, Clone)]
 pub struct Interpreter {
     globals: Rc<RefCell<Environment>>,
     environment: Rc<RefCell<Environment>>,
     locals: HashMap<usize, usize>,
+    next_expression_id: usize,
+    /// The file whose code is running, if it came from one.
+    file: Option<Rc<Path>>,
+    /// Files whose top-level code is running, the main script first, to catch import cycles.
+    loading: Vec<Rc<Path>>,
+    modules: HashMap<Rc<Path>, Rc<RloxModule>>,
+    /// Shared by every module, so one `math.seed` makes the whole program reproducible.
+    rng: Rng,
+    /// Whether scripts may use files, standard input, the environment and the process.
+    io: bool,
+    script_args: Vec<String>,
+    /// The calls in progress, outermost first, for the traces of uncaught errors.
+    frames: Vec<CallFrame>,
+    max_call_depth: usize,
+    budget: Budget,
+    steps: u64,
+    allocations: u64,
+    cancel: CancelHandle,
 }
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -22,12 +75,53 @@ pub enum Value {
     Number(f64),
     Bool(bool),
     Func(Rc<dyn RloxCallable>),
+    List(Rc<RefCell<Vec<Rc<Value>>>>),
+    Map(Rc<RefCell<RloxMap>>),
+    Module(Rc<RloxModule>),
     Nil,
 }
 
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match &self {
+        self.write(f, false, &mut vec![])
+    }
+}
+
+impl Value {
+    /// What `type` returns for the value, and what error messages call it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Func(function) => match function.kind() {
+                CallableKind::Function => "function",
+                CallableKind::Native => "native",
+            },
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Module(_) => "module",
+            Value::Nil => "nil",
+        }
+    }
+
+    // anything except null and false is true
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Bool(false) | Value::Nil)
+    }
+
+    /// Formats the value as it appears inside a collection, with strings quoted.
+    pub fn repr(&self) -> String {
+        let mut output = String::new();
+        self.write(&mut output, true, &mut vec![])
+            .expect("writing to a String can't fail");
+        output
+    }
+
+    /// Writes the value, printing `[...]` or `{...}` for a list or map inside itself or
+    /// nested deeper than `MAX_VALUE_DEPTH`. `path` holds the lists and maps being written.
+    fn write(&self, f: &mut impl Write, quoted: bool, path: &mut Vec<*const ()>) -> std::fmt::Result {
+        match self {
             Value::Nil => write!(f, "nil"),
             Value::Number(num) => {
                 if num.fract() == 0.0 {
@@ -37,85 +131,509 @@ impl Display for Value {
                 }
             }
             Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) if quoted => write!(f, "{:?}", s),
             Value::Str(s) => write!(f, "{}", s),
-            Value::Func(func) => write!(f, "{:?}", func),
+            Value::Func(func) => write!(f, "{}", func),
+            Value::List(list) => {
+                let pointer = Rc::as_ptr(list) as *const ();
+                if path.len() >= MAX_VALUE_DEPTH || path.contains(&pointer) {
+                    return write!(f, "[...]");
+                }
+                path.push(pointer);
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    nested(|| element.write(f, true, path))?;
+                }
+                path.pop();
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                let pointer = Rc::as_ptr(map) as *const ();
+                if path.len() >= MAX_VALUE_DEPTH || path.contains(&pointer) {
+                    return write!(f, "{{...}}");
+                }
+                path.push(pointer);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().entries().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    nested(|| key.write(f, true, path))?;
+                    write!(f, ": ")?;
+                    nested(|| value.write(f, true, path))?;
+                }
+                path.pop();
+                write!(f, "}}")
+            }
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+        }
+    }
+
+    /// Compares values the way `==` does, or returns None for lists or maps nested deeper
+    /// than `MAX_VALUE_DEPTH`.
+    pub fn try_eq(&self, other: &Value) -> Option<bool> {
+        self.equals(other, &mut vec![])
+    }
+
+    /// `path` holds the pairs of lists and maps being compared; meeting a pair again means
+    /// it holds itself the same way on both sides.
+    fn equals(&self, other: &Value, path: &mut Vec<(*const (), *const ())>) -> Option<bool> {
+        match (self, other) {
+            (Value::List(a), Value::List(b)) => {
+                let pair = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+                if Rc::ptr_eq(a, b) || path.contains(&pair) {
+                    return Some(true);
+                }
+                if path.len() >= MAX_VALUE_DEPTH {
+                    return None;
+                }
+                let (a, b) = (a.borrow(), b.borrow());
+                if a.len() != b.len() {
+                    return Some(false);
+                }
+                path.push(pair);
+                for (a, b) in a.iter().zip(b.iter()) {
+                    if !nested(|| a.equals(b, path))? {
+                        path.pop();
+                        return Some(false);
+                    }
+                }
+                path.pop();
+                Some(true)
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                let pair = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+                if Rc::ptr_eq(a, b) || path.contains(&pair) {
+                    return Some(true);
+                }
+                if path.len() >= MAX_VALUE_DEPTH {
+                    return None;
+                }
+                path.push(pair);
+                let mut too_deep = false;
+                let equal = a.borrow().eq_by(&b.borrow(), |a, b| {
+                    nested(|| a.equals(b, path)).unwrap_or_else(|| {
+                        too_deep = true;
+                        false
+                    })
+                });
+                path.pop();
+                (!too_deep).then_some(equal)
+            }
+            (Value::Bool(a), Value::Bool(b)) => Some(a == b),
+            (Value::Nil, Value::Nil) => Some(true),
+            (Value::Number(a), Value::Number(b)) => Some(a == b),
+            (Value::Str(a), Value::Str(b)) => Some(a == b),
+            (Value::Func(a), Value::Func(b)) => Some(Rc::ptr_eq(a, b)),
+            (Value::Module(a), Value::Module(b)) => Some(Rc::ptr_eq(a, b)),
+            _ => Some(false),
+        }
+    }
+
+    /// Moves out the elements of a list or the keys and values of a map nothing else holds.
+    fn take_contents(&mut self, into: &mut Vec<Rc<Value>>) {
+        match self {
+            Value::List(list) if Rc::strong_count(list) == 1 => into.append(&mut list.borrow_mut()),
+            Value::Map(map) if Rc::strong_count(map) == 1 => {
+                for (key, value) in map.borrow_mut().take_entries() {
+                    into.push(key);
+                    into.push(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn new_list(elements: Vec<Rc<Value>>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn new_map(map: RloxMap) -> Value {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+}
+
+/// Checks that `index` is a whole number addressing one of `len` elements.
+pub fn list_index(index: &Value, len: usize, lexeme: &str) -> Result<usize, RloxError> {
+    let error = |message: String| RloxError::RuntimeError {
+        lexeme: lexeme.to_string(),
+        message,
+    };
+    match index {
+        Value::Number(n) if n.fract() != 0.0 || n.is_nan() => {
+            Err(error(format!("List index {} is not a whole number.", index)))
+        }
+        Value::Number(n) if *n < 0.0 => Err(error(format!("List index {} is negative.", index))),
+        Value::Number(n) if *n >= len as f64 => Err(error(format!(
+            "List index {} is out of bounds for a list of length {}.",
+            index, len
+        ))),
+        Value::Number(n) => Ok(*n as usize),
+        _ => Err(error(format!(
+            "List index must be a number, not a {}.",
+            index.type_name()
+        ))),
+    }
+}
+
+impl Drop for Value {
+    /// Frees the lists and maps only this value holds one at a time, since freeing them
+    /// recursively overflows the stack on lists nested deep enough.
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_contents(&mut pending);
+        while let Some(value) = pending.pop() {
+            if let Ok(mut value) = Rc::try_unwrap(value) {
+                value.take_contents(&mut pending);
+            }
         }
     }
 }
 
+/// Values nested too deep to compare count as unequal; the `==` operator reports them instead.
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Value::Bool(a), Value::Bool(b)) => a == b,
-            (Value::Nil, Value::Nil) => true,
-            (Value::Number(a), Value::Number(b)) => a == b,
-            (Value::Str(a), Value::Str(b)) => a == b,
-            _ => false,
-        }
+        self.try_eq(other).unwrap_or(false)
     }
 }
 
 impl Interpreter {
+    /// An interpreter whose scripts can only compute, as suits embedding.
     pub fn new() -> Self {
-        let globals = Rc::new(RefCell::new(Environment::default()));
-        globals
-            .borrow_mut()
-            .define("clock", Rc::new(Value::Func(Rc::new(Clock {}))));
-        
+        Self::build(false)
+    }
+    /// An interpreter whose scripts also get the I/O natives and may import modules.
+    pub fn with_io() -> Self {
+        Self::build(true)
+    }
+    fn build(io: bool) -> Self {
+        let globals = Self::new_globals(io);
         Self {
             globals: globals.clone(),
             environment: globals,
             locals: HashMap::new(),
+            next_expression_id: 0,
+            file: None,
+            loading: vec![],
+            modules: HashMap::new(),
+            rng: Rng::from_time(),
+            io,
+            script_args: vec![],
+            frames: vec![],
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            budget: Budget::default(),
+            steps: 0,
+            allocations: 0,
+            cancel: CancelHandle::default(),
         }
     }
-    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RloxError> {
-        for statement in statements {
-            self.execute(&statement)?
+    /// A global environment holding only the builtins, as every script and module starts with.
+    fn new_globals(io: bool) -> Rc<RefCell<Environment>> {
+        let globals = Rc::new(RefCell::new(Environment::default()));
+        globals
+            .borrow_mut()
+            .define("clock", Rc::new(Value::Func(Rc::new(Clock {}))));
+        globals
+            .borrow_mut()
+            .define("math", Rc::new(Value::Module(Rc::new(math_module()))));
+        define_natives(&globals.borrow());
+        define_string_natives(&globals.borrow());
+        define_json_natives(&globals.borrow());
+        if io {
+            define_io_natives(&globals.borrow());
+        }
+        globals
+    }
+    /// Names the file the main script was read from, which its imports are relative to.
+    pub fn set_script_path(&mut self, path: &Path) {
+        let path: Rc<Path> = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()).into();
+        self.file = Some(path.clone());
+        self.loading = vec![path];
+    }
+    pub fn module_scope(&self) -> ModuleScope {
+        ModuleScope {
+            globals: self.globals.clone(),
+            file: self.file.clone(),
+        }
+    }
+    /// Switches to another top level, such as the module a called function belongs to,
+    /// returning the current one to switch back to.
+    pub fn enter_scope(&mut self, scope: ModuleScope) -> ModuleScope {
+        ModuleScope {
+            globals: std::mem::replace(&mut self.globals, scope.globals),
+            file: std::mem::replace(&mut self.file, scope.file),
+        }
+    }
+    pub fn has_io(&self) -> bool {
+        self.io
+    }
+    /// The command line arguments following the script.
+    pub fn script_args(&self) -> &[String] {
+        &self.script_args
+    }
+    pub fn set_script_args(&mut self, args: &[String]) {
+        self.script_args = args.to_vec();
+    }
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+    /// Limits the work done from now on, starting the count afresh. A cancelled
+    /// interpreter stays cancelled.
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+        self.steps = 0;
+        self.allocations = 0;
+    }
+    /// A handle other threads can stop this interpreter's scripts with.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+    pub fn set_cancel_handle(&mut self, cancel: CancelHandle) {
+        self.cancel = cancel;
+    }
+    /// Counts one step against the budget, checking the clock only now and then.
+    fn step(&mut self) -> Result<(), RloxError> {
+        self.steps += 1;
+        if let Some(max) = self.budget.max_steps.filter(|&max| self.steps > max) {
+            return Err(RloxError::Exhausted(Limit::Steps(max)));
+        }
+        if self.cancel.is_cancelled() {
+            return Err(RloxError::Exhausted(Limit::Cancelled));
+        }
+        let late = self.steps.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+            && self.budget.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if late {
+            return Err(RloxError::Exhausted(Limit::Deadline));
         }
         Ok(())
     }
+    /// Wraps a list, map, string or function that was just created, counting it against
+    /// the budget.
+    pub fn allocate(&mut self, value: Value) -> Result<Rc<Value>, RloxError> {
+        self.count_allocations(1)?;
+        Ok(Rc::new(value))
+    }
+    /// Counts `count` lists, maps, strings or functions created at once against the budget.
+    pub fn count_allocations(&mut self, count: usize) -> Result<(), RloxError> {
+        self.allocations += count as u64;
+        match self.budget.max_allocations {
+            Some(max) if self.allocations > max => Err(RloxError::Exhausted(Limit::Allocations(max))),
+            _ => Ok(()),
+        }
+    }
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+    pub fn globals(&self) -> Rc<RefCell<Environment>> {
+        self.globals.clone()
+    }
+    /// The id the next parsed expression should start numbering from.
+    pub fn next_expression_id(&self) -> usize {
+        self.next_expression_id
+    }
+    pub fn set_next_expression_id(&mut self, id: usize) {
+        self.next_expression_id = id;
+    }
+    /// Runs top-level statements. Whatever happens, execution ends back in the global environment.
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RloxError> {
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+        self.environment = self.globals.clone();
+        result
+    }
+    /// Evaluates a top-level expression, ending back in the global environment like `interpret`.
+    pub fn interpret_expression(&mut self, expr: &Expr) -> Result<Rc<Value>, RloxError> {
+        let result = self.evaluate(expr);
+        self.environment = self.globals.clone();
+        result
+    }
+    /// Evaluates an expression inside `environment`, such as a parameter default inside
+    /// the environment of the call it belongs to.
+    pub fn evaluate_in(
+        &mut self,
+        expr: &Expr,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Rc<Value>, RloxError> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = self.evaluate(expr);
+        self.environment = previous;
+        result
+    }
+    /// Evaluates an expression. Runtime errors raised by it become catchable error values
+    /// located at its line, unless an inner expression has already located them.
     fn evaluate(&mut self, expr: &Expr) -> Result<Rc<Value>, RloxError> {
+        self.step()?;
+        self.evaluate_unlocated(expr).map_err(|error| match Self::line(expr) {
+            Some(line) => error.located(line, &self.frames),
+            None => error,
+        })
+    }
+
+    /// The source line of an expression, for those that remember one.
+    fn line(expr: &Expr) -> Option<usize> {
+        match expr {
+            Expr::Binary { operator, .. }
+            | Expr::Logical { operator, .. }
+            | Expr::Unary { operator, .. }
+            | Expr::CompoundAssign { operator, .. }
+            | Expr::CompoundSetIndex { operator, .. } => Some(operator.line),
+            Expr::Call { paren, .. } => Some(paren.line),
+            Expr::Map { brace, .. } => Some(brace.line),
+            Expr::Index { bracket, .. } | Expr::SetIndex { bracket, .. } => Some(bracket.line),
+            Expr::Variable { line, .. } | Expr::Assign { line, .. } => Some(*line),
+            Expr::Get { name, .. } => Some(name.line),
+            _ => None,
+        }
+    }
+
+    fn evaluate_unlocated(&mut self, expr: &Expr) -> Result<Rc<Value>, RloxError> {
         match expr {
             Expr::Nil => Ok(Rc::new(Value::Nil)),
             Expr::Number(n) => Ok(Rc::new(Value::Number(*n))),
-            Expr::String(s) => Ok(Rc::new(Value::Str(s.to_string()))),
+            Expr::String(s) => self.allocate(Value::Str(s.to_string())),
             Expr::Boolean(b) => Ok(Rc::new(Value::Bool(*b))),
             Expr::Grouping { expression } => self.evaluate(expression),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.evaluate(condition)?;
+                if self.is_truthy(&condition) {
+                    self.evaluate(then_branch)
+                } else {
+                    self.evaluate(else_branch)
+                }
+            }
             Expr::Binary {
                 left,
                 operator,
                 right,
-            } => self.binary_expr(left, &operator.token_type, right),
-            Expr::Unary { operator, right } => self.unary_expr(&operator.token_type, right),
+            } => self.binary_expr(left, operator, right),
+            Expr::Unary { operator, right } => self.unary_expr(operator, right),
             Expr::Logical {
                 left,
                 operator,
                 right,
             } => self.logical_expr(left, &operator.token_type, right),
-            Expr::Variable { id, name } => {
-                let depth = self.locals.get(id).copied();
-                if let Some(depth) = depth {
-                    self.environment.borrow().get_at(depth, name)
-                } else {
-                    self.globals.borrow().get_at(0, name)
-                }
+            Expr::Variable { id, name, .. } => self.look_up_variable(*id, name),
+            Expr::Assign { id, name, value, .. } => {
+                let value = self.evaluate(value)?;
+                self.assign_variable(*id, name, value.clone())?;
+                Ok(value)
             }
-            Expr::Assign { id, name, value } => {
+            Expr::CompoundAssign {
+                id,
+                name,
+                operator,
+                value,
+                postfix,
+            } => {
+                let old = self.look_up_variable(*id, name)?;
                 let value = self.evaluate(value)?;
-                let depth = self.locals.get(id).copied();
-
-                if let Some(depth) = depth {
-                    self.environment
-                        .borrow_mut()
-                        .assign_at(&depth, name, value.clone())?;
-                } else {
-                    self.globals
-                        .borrow_mut()
-                        .assign_at(&0, name, value.clone())?;
+                let new = self.compound(&old, operator, &value)?;
+                self.assign_variable(*id, name, new.clone())?;
+                Ok(if *postfix { old } else { new })
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => self.call_expr(callee, paren, arguments),
+            Expr::Lambda { parameters, body } => {
+                let function = RloxFunction::new(
+                    None,
+                    parameters.clone(),
+                    body.clone(),
+                    self.environment.clone(),
+                    self.module_scope(),
+                );
+                self.allocate(Value::Func(Rc::new(function)))
+            }
+            Expr::Get { object, name } => match self.evaluate(object)?.as_ref() {
+                Value::Module(module) => {
+                    let value = module.globals.borrow().get(&name.lexeme);
+                    value.map_err(|_| RloxError::RuntimeError {
+                        lexeme: name.lexeme.clone(),
+                        message: format!("Module {} has no '{}'.", module.name, name.lexeme),
+                    })
+                }
+                value => Err(RloxError::RuntimeError {
+                    lexeme: name.lexeme.clone(),
+                    message: format!("Only modules have members, not a {}.", value.type_name()),
+                }),
+            },
+            Expr::List { elements } => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.evaluate(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.allocate(Value::new_list(elements))
+            }
+            Expr::Map {
+                brace,
+                keys,
+                values,
+            } => {
+                let mut map = RloxMap::default();
+                for (key, value) in keys.iter().zip(values) {
+                    let key = self.evaluate(key)?;
+                    let value = self.evaluate(value)?;
+                    map.insert(key, value, &brace.lexeme)?;
                 }
+                self.allocate(Value::new_map(map))
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                Self::get_index(&object, &index, bracket)
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+                Self::set_index(&object, index, value.clone(), bracket)?;
                 Ok(value)
             }
-            Expr::Call { callee, arguments } => self.call_expr(callee, arguments),
+            Expr::CompoundSetIndex {
+                object,
+                bracket,
+                index,
+                operator,
+                value,
+                postfix,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let old = Self::get_index(&object, &index, bracket)?;
+                let value = self.evaluate(value)?;
+                let new = self.compound(&old, operator, &value)?;
+                Self::set_index(&object, index, new.clone(), bracket)?;
+                Ok(if *postfix { old } else { new })
+            }
+        }
+    }
+
+    fn not_indexable(value: &Value, bracket: &Token) -> RloxError {
+        RloxError::RuntimeError {
+            lexeme: bracket.lexeme.clone(),
+            message: format!("Only lists and maps can be indexed, not a {}.", value.type_name()),
         }
     }
 
@@ -127,30 +645,44 @@ impl Interpreter {
     ) -> Result<Rc<Value>, RloxError> {
         let left = self.evaluate(left)?;
 
-        if *operator == TokenType::Or {
-            if self.is_truthy(&left) {
-                return Ok(left);
-            }
-        } else {
-            if !self.is_truthy(&left) {
-                return Ok(left);
-            }
+        let short_circuits = match operator {
+            TokenType::Or => self.is_truthy(&left),
+            TokenType::QuestionQuestion => !matches!(left.as_ref(), Value::Nil),
+            _ => !self.is_truthy(&left),
+        };
+        if short_circuits {
+            return Ok(left);
         }
         self.evaluate(right)
     }
     fn binary_expr(
         &mut self,
         left: &Expr,
-        token_type: &TokenType,
+        operator: &Token,
         right: &Expr,
     ) -> Result<Rc<Value>, RloxError> {
-        let left = &*self.evaluate(left)?;
-        let right = &*self.evaluate(right)?;
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        self.binary_values(&left, &operator.token_type, &right, &operator.lexeme)
+    }
 
+    fn binary_values(
+        &mut self,
+        left: &Value,
+        token_type: &TokenType,
+        right: &Value,
+        lexeme: &str,
+    ) -> Result<Rc<Value>, RloxError> {
         match (left, token_type, right) {
             (Value::Number(l), TokenType::Star, Value::Number(r)) => {
                 Ok(Rc::new(Value::Number(l * r)))
             }
+            (Value::Number(l), TokenType::Slash, Value::Number(r)) => {
+                Ok(Rc::new(Value::Number(l / r)))
+            }
+            (Value::Number(l), TokenType::Percent, Value::Number(r)) => {
+                Ok(Rc::new(Value::Number(l % r)))
+            }
             (Value::Number(l), TokenType::Minus, Value::Number(r)) => {
                 Ok(Rc::new(Value::Number(l - r)))
             }
@@ -158,52 +690,125 @@ impl Interpreter {
                 Ok(Rc::new(Value::Number(l + r)))
             }
             (Value::Number(l), TokenType::Greater, Value::Number(r)) => {
-                Ok(Rc::new(Value::Bool(l.gt(&r))))
+                Ok(Rc::new(Value::Bool(l.gt(r))))
             }
             (Value::Number(l), TokenType::GreaterEqual, Value::Number(r)) => {
-                Ok(Rc::new(Value::Bool(l.ge(&r))))
+                Ok(Rc::new(Value::Bool(l.ge(r))))
             }
             (Value::Number(l), TokenType::Less, Value::Number(r)) => {
-                Ok(Rc::new(Value::Bool(l.lt(&r))))
+                Ok(Rc::new(Value::Bool(l.lt(r))))
             }
             (Value::Number(l), TokenType::LessEqual, Value::Number(r)) => {
-                Ok(Rc::new(Value::Bool(l.le(&r))))
+                Ok(Rc::new(Value::Bool(l.le(r))))
             }
             (Value::Str(l), TokenType::Plus, Value::Str(r)) => {
-                Ok(Rc::new(Value::Str(l.clone() + &r)))
-            }
-            (Value::Number(l), TokenType::EqualEqual, Value::Number(r)) => {
-                Ok(Rc::new(Value::Bool(l.eq(&r))))
+                self.allocate(Value::Str(l.clone() + r))
             }
-            (Value::Number(l), TokenType::BangEqual, Value::Number(r)) => {
-                Ok(Rc::new(Value::Bool(!l.eq(&r))))
+            (l, TokenType::EqualEqual | TokenType::BangEqual, r) => match l.try_eq(r) {
+                Some(equal) if *token_type == TokenType::EqualEqual => Ok(Rc::new(Value::Bool(equal))),
+                Some(equal) => Ok(Rc::new(Value::Bool(!equal))),
+                None => Err(RloxError::RuntimeError {
+                    lexeme: lexeme.to_string(),
+                    message: format!(
+                        "Can't compare lists or maps nested more than {} deep.",
+                        MAX_VALUE_DEPTH
+                    ),
+                }),
+            },
+            (l, TokenType::Plus, r) => Err(RloxError::RuntimeError {
+                lexeme: lexeme.to_string(),
+                message: format!(
+                    "Can't add a {} and a {}, operands must be two numbers or two strings.",
+                    l.type_name(),
+                    r.type_name()
+                ),
+            }),
+            (l, _, r) => Err(RloxError::RuntimeError {
+                lexeme: lexeme.to_string(),
+                message: format!(
+                    "Operands must be numbers, not a {} and a {}.",
+                    l.type_name(),
+                    r.type_name()
+                ),
+            }),
+        }
+    }
+
+    /// Applies the arithmetic behind `+=`, `++` and the like.
+    fn compound(&mut self, old: &Value, operator: &Token, value: &Value) -> Result<Rc<Value>, RloxError> {
+        let token_type = match operator.token_type {
+            TokenType::PlusEqual | TokenType::PlusPlus => TokenType::Plus,
+            TokenType::MinusEqual | TokenType::MinusMinus => TokenType::Minus,
+            TokenType::StarEqual => TokenType::Star,
+            TokenType::SlashEqual => TokenType::Slash,
+            _ => TokenType::Percent,
+        };
+        self.binary_values(old, &token_type, value, &operator.lexeme)
+    }
+
+    fn look_up_variable(&self, id: usize, name: &str) -> Result<Rc<Value>, RloxError> {
+        match self.locals.get(&id) {
+            Some(depth) => self.environment.borrow().get_at(*depth, name),
+            None => self.globals.borrow().get_at(0, name),
+        }
+    }
+
+    fn assign_variable(&self, id: usize, name: &str, value: Rc<Value>) -> Result<(), RloxError> {
+        match self.locals.get(&id) {
+            Some(depth) => self.environment.borrow_mut().assign_at(depth, name, value),
+            None => self.globals.borrow_mut().assign_at(&0, name, value),
+        }
+    }
+
+    fn get_index(object: &Value, index: &Value, bracket: &Token) -> Result<Rc<Value>, RloxError> {
+        match object {
+            Value::List(list) => {
+                let list = list.borrow();
+                let i = list_index(index, list.len(), &bracket.lexeme)?;
+                Ok(list[i].clone())
             }
-            (Value::Bool(l), TokenType::EqualEqual, Value::Bool(r)) => {
-                Ok(Rc::new(Value::Bool(l.eq(&r))))
+            Value::Map(map) => Ok(map
+                .borrow()
+                .get(index, &bracket.lexeme)?
+                .unwrap_or_else(|| Rc::new(Value::Nil))),
+            value => Err(Self::not_indexable(value, bracket)),
+        }
+    }
+
+    fn set_index(
+        object: &Value,
+        index: Rc<Value>,
+        value: Rc<Value>,
+        bracket: &Token,
+    ) -> Result<(), RloxError> {
+        match object {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let i = list_index(&index, list.len(), &bracket.lexeme)?;
+                list[i] = value;
             }
-            (Value::Bool(l), TokenType::BangEqual, Value::Bool(r)) => {
-                Ok(Rc::new(Value::Bool(!l.eq(&r))))
+            Value::Map(map) => {
+                map.borrow_mut().insert(index, value, &bracket.lexeme)?;
             }
-            _ => Err(RloxError::InterpreterError),
+            value => return Err(Self::not_indexable(value, bracket)),
         }
+        Ok(())
     }
-    fn unary_expr(&mut self, token_type: &TokenType, expr: &Expr) -> Result<Rc<Value>, RloxError> {
+
+    fn unary_expr(&mut self, operator: &Token, expr: &Expr) -> Result<Rc<Value>, RloxError> {
         let right = self.evaluate(expr)?;
-        match token_type {
-            TokenType::Minus => match *right {
-                Value::Number(n) => Ok(Rc::new(Value::Number(-n))),
-                _ => Err(RloxError::InterpreterError),
-            },
-            TokenType::Bang => Ok(Rc::new(Value::Bool(!self.is_truthy(&right)))),
-            _ => Err(RloxError::InterpreterError),
+        match (&operator.token_type, right.as_ref()) {
+            (TokenType::Minus, Value::Number(n)) => Ok(Rc::new(Value::Number(-n))),
+            (TokenType::Bang, right) => Ok(Rc::new(Value::Bool(!self.is_truthy(right)))),
+            (_, right) => Err(RloxError::RuntimeError {
+                lexeme: operator.lexeme.clone(),
+                message: format!("Operand must be a number, not a {}.", right.type_name()),
+            }),
         }
     }
     // anything except null and false is true
     fn is_truthy(&self, right: &Value) -> bool {
-        match *right {
-            Value::Bool(false) | Value::Nil => false,
-            _ => true,
-        }
+        right.is_truthy()
     }
 
     pub fn stringify(value: &Value) -> String {
@@ -212,7 +817,7 @@ impl Interpreter {
             Value::Number(n) => n.to_string(),
             Value::Bool(b) => b.to_string(),
             Value::Nil => "nil".to_string(),
-            Value::Func(_) => "<func>".to_string(),
+            Value::Func(_) | Value::List(_) | Value::Map(_) | Value::Module(_) => value.to_string(),
         }
     }
     pub fn add_scopes(&mut self, scopes: HashMap<usize, usize>) {
@@ -222,9 +827,10 @@ impl Interpreter {
     }
 
     fn execute(&mut self, statement: &Stmt) -> Result<(), RloxError> {
+        self.step()?;
         match statement {
             Stmt::Print { expression } => {
-                println!("{:?}", self.evaluate(expression)?);
+                println!("{}", self.evaluate(expression)?);
                 Ok(())
             }
             Stmt::Expression { expression } => {
@@ -233,12 +839,17 @@ impl Interpreter {
             }
             Stmt::Var { name, initializer } => {
                 let value = if let Some(expression) = initializer {
-                    self.evaluate(&expression)?
+                    self.evaluate(expression)?
                 } else {
                     Rc::new(Value::Nil)
                 };
 
-                self.environment.borrow_mut().define(&name, value);
+                self.environment.borrow_mut().define(name, value);
+                Ok(())
+            }
+            Stmt::Const { name, initializer } => {
+                let value = self.evaluate(initializer)?;
+                self.environment.borrow().define_const(name, value);
                 Ok(())
             }
             Stmt::Block { statements } => self.execute_block(
@@ -260,24 +871,89 @@ impl Interpreter {
                 }
             }
             Stmt::While { condition, body } => {
-                let mut evaluated_condition = self.evaluate(&condition)?;
+                let mut evaluated_condition = self.evaluate(condition)?;
                 while self.is_truthy(&evaluated_condition) {
                     self.execute(body)?;
-                    evaluated_condition = self.evaluate(&condition)?;
+                    evaluated_condition = self.evaluate(condition)?;
                 }
                 Ok(())
             }
+            Stmt::Match {
+                keyword,
+                subject,
+                arms,
+            } => {
+                let subject = self.evaluate(subject)?;
+                self.execute_match(keyword, &subject, arms)
+            }
+            Stmt::Import {
+                keyword,
+                path,
+                alias,
+                names,
+            } => self
+                .import(path, alias.as_deref(), names)
+                .map_err(|error| error.located(keyword.line, &self.frames)),
+            Stmt::Throw { keyword, value } => {
+                let value = self.evaluate(value)?;
+                Err(RloxError::Thrown {
+                    value,
+                    line: keyword.line,
+                    trace: self.frames.clone(),
+                })
+            }
+            Stmt::Try {
+                keyword,
+                body,
+                catch_name,
+                catch_body,
+                finally_body,
+            } => {
+                let environment = Environment::new(self.environment.clone());
+                let mut result = self.execute_block(body, Rc::new(RefCell::new(environment)));
+                if let (Some(name), Some(handler)) = (catch_name, catch_body) {
+                    // errors no expression located, such as iterating a number, get the try's line
+                    result = match result.map_err(|error| error.located(keyword.line, &self.frames)) {
+                        Err(RloxError::Thrown { value, .. }) => {
+                            let environment = Environment::new(self.environment.clone());
+                            environment.define(name, value);
+                            self.execute_block(handler, Rc::new(RefCell::new(environment)))
+                        }
+                        other => other,
+                    };
+                }
+                // runs on success, error and return alike, and its own failure wins
+                if let Some(finally_body) = finally_body {
+                    let environment = Environment::new(self.environment.clone());
+                    self.execute_block(finally_body, Rc::new(RefCell::new(environment)))?;
+                }
+                result
+            }
+            Stmt::ForIn {
+                keyword,
+                name,
+                iterable,
+                body,
+            } => {
+                let iterable = self.evaluate(iterable)?;
+                // located here, while the frame of the function running the loop is still on the stack
+                self.for_in(keyword.line, name, &iterable, body)
+                    .map_err(|error| error.located(keyword.line, &self.frames))
+            }
             Stmt::Function {
                 name,
                 parameters,
                 body,
             } => {
-                let function = Rc::new(Value::Func(Rc::new(RloxFunction::new(
+                let function = RloxFunction::new(
+                    Some(name.clone()),
                     parameters.clone(),
                     body.clone(),
                     self.environment.clone(),
-                ))));
-                self.environment.borrow_mut().define(&name, function);
+                    self.module_scope(),
+                );
+                let function = self.allocate(Value::Func(Rc::new(function)))?;
+                self.environment.borrow_mut().define(name, function);
                 Ok(())
             }
             Stmt::Return { value } => {
@@ -291,40 +967,259 @@ impl Interpreter {
         }
     }
 
+    /// Runs `body` once per item of `iterable`, each time in a fresh scope holding the item,
+    /// so closures created in the body capture that iteration's value.
+    ///
+    /// Lists are walked by position, so elements pushed while looping are visited. Maps yield
+    /// the keys present when the loop starts, strings their characters, and a function is
+    /// called with no arguments for each item until it returns nil.
+    fn for_in(&mut self, line: usize, name: &str, iterable: &Value, body: &Stmt) -> Result<(), RloxError> {
+        match iterable {
+            Value::List(list) => {
+                let mut i = 0;
+                loop {
+                    let item = match list.borrow().get(i) {
+                        Some(item) => item.clone(),
+                        None => break,
+                    };
+                    self.execute_iteration(name, item, body)?;
+                    i += 1;
+                }
+            }
+            Value::Map(map) => {
+                let keys: Vec<Rc<Value>> = map.borrow().entries().map(|(key, _)| key.clone()).collect();
+                for key in keys {
+                    self.execute_iteration(name, key, body)?;
+                }
+            }
+            Value::Str(s) => {
+                for c in s.chars() {
+                    let c = self.allocate(Value::Str(c.to_string()))?;
+                    self.execute_iteration(name, c, body)?;
+                }
+            }
+            Value::Func(function) if function.arity().accepts(0) => loop {
+                let item = self.call_value(function, line, &[], &[])?;
+                if let Value::Nil = item.as_ref() {
+                    break;
+                }
+                self.execute_iteration(name, item, body)?;
+            },
+            value => {
+                return Err(RloxError::RuntimeError {
+                    lexeme: name.to_string(),
+                    message: format!("Can't iterate over a {}.", value.type_name()),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the module at `path` and binds it to `alias`, or binds the listed names from it,
+    /// in the current environment.
+    fn import(&mut self, path: &str, alias: Option<&str>, names: &[String]) -> Result<(), RloxError> {
+        let module = self.load_module(path)?;
+        let environment = self.environment.borrow();
+        if let Some(alias) = alias {
+            environment.define(alias, Rc::new(Value::Module(module.clone())));
+        }
+        for name in names {
+            let value = module.globals.borrow().get(name);
+            let value = value.map_err(|_| RloxError::RuntimeError {
+                lexeme: name.clone(),
+                message: format!("Module {} has no '{}'.", module.name, name),
+            })?;
+            environment.define(name, value);
+        }
+        Ok(())
+    }
+
+    /// Returns the module at `path`, relative to the running file, running it the first
+    /// time it is imported. Its top-level code runs in globals of its own.
+    fn load_module(&mut self, path: &str) -> Result<Rc<RloxModule>, RloxError> {
+        let error = |message: String| RloxError::RuntimeError {
+            lexeme: path.to_string(),
+            message,
+        };
+        // modules are files, so only interpreters allowed to touch files may load them
+        if !self.io {
+            return Err(error(format!("Can't import \"{}\" without file access.", path)));
+        }
+        let base = self.file.as_deref().and_then(Path::parent).unwrap_or(Path::new(""));
+        let full: Rc<Path> = fs::canonicalize(base.join(path))
+            .map_err(|e| error(format!("Can't import \"{}\": {}.", path, e)))?
+            .into();
+        if let Some(module) = self.modules.get(&full) {
+            return Ok(module.clone());
+        }
+        if let Some(start) = self.loading.iter().position(|file| *file == full) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain([&full])
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(error(format!("Import cycle: {}.", cycle.join(" -> "))));
+        }
+
+        let source = fs::read_to_string(&full)
+            .map_err(|e| error(format!("Can't read \"{}\": {}.", path, e)))?;
+        let statements = self.compile(&source)?;
+        let globals = Self::new_globals(self.io);
+        let importer = self.enter_scope(ModuleScope {
+            globals: globals.clone(),
+            file: Some(full.clone()),
+        });
+        let environment = std::mem::replace(&mut self.environment, globals.clone());
+        self.loading.push(full.clone());
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+        self.loading.pop();
+        self.environment = environment;
+        self.enter_scope(importer);
+        result?;
+
+        let name = full
+            .file_stem()
+            .map_or_else(|| path.to_string(), |stem| stem.to_string_lossy().into_owned());
+        let module = Rc::new(RloxModule { name, globals });
+        self.modules.insert(full, module.clone());
+        Ok(module)
+    }
+
+    /// Scans, parses and resolves a module's source. Its expression ids continue the
+    /// interpreter's, so they never collide with those of the code importing it.
+    fn compile(&mut self, source: &str) -> Result<Vec<Stmt>, RloxError> {
+        let tokens = Scanner::default().scan_tokens(source.to_string())?;
+        let mut parser = Parser::new(tokens, self.next_expression_id);
+        let statements = parser.parse();
+        self.next_expression_id = parser.next_id();
+        let statements = statements?;
+        let depths = Resolver::new().resolve(&statements)?;
+        self.add_scopes(depths);
+        Ok(statements)
+    }
+
+    /// Runs the first arm whose pattern matches and whose guard holds. The arm's bindings
+    /// live in a scope of their own, which the guard sees too.
+    fn execute_match(
+        &mut self,
+        keyword: &Token,
+        subject: &Rc<Value>,
+        arms: &[MatchArm],
+    ) -> Result<(), RloxError> {
+        for arm in arms {
+            let mut bound = vec![];
+            if !arm.pattern.matches(self, subject, &mut bound)? {
+                continue;
+            }
+            let environment = Environment::new(self.environment.clone());
+            for (name, value) in bound {
+                environment.define(&name, value);
+            }
+            let environment = Rc::new(RefCell::new(environment));
+            if let Some(guard) = &arm.guard {
+                let guard = self.evaluate_in(guard, environment.clone())?;
+                if !guard.is_truthy() {
+                    continue;
+                }
+            }
+            return self.execute_block(std::slice::from_ref(&arm.body), environment);
+        }
+        Err(RloxError::RuntimeError {
+            lexeme: keyword.lexeme.clone(),
+            message: format!("No match arm matches {}.", subject.repr()),
+        }
+        .located(keyword.line, &self.frames))
+    }
+
+    fn execute_iteration(&mut self, name: &str, item: Rc<Value>, body: &Stmt) -> Result<(), RloxError> {
+        let environment = Environment::new(self.environment.clone());
+        environment.define(name, item);
+        self.execute_block(
+            std::slice::from_ref(body),
+            Rc::new(RefCell::new(environment)),
+        )
+    }
+
     pub fn execute_block(
         &mut self,
-        statements: &Vec<Stmt>,
+        statements: &[Stmt],
         new_env: Rc<RefCell<Environment>>,
     ) -> Result<(), RloxError> {
-        let previous = self.environment.clone();
-        self.environment = new_env;
-
-        for statement in statements {
-            self.execute(statement).map_err(|err| {
-                self.environment = previous.clone();
-                err
-            })?;
-        }
+        let previous = std::mem::replace(&mut self.environment, new_env);
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
         self.environment = previous;
-        Ok(())
+        result
     }
 
-    fn call_expr(&mut self, callee: &Expr, arguments: &Vec<Expr>) -> Result<Rc<Value>, RloxError> {
+    fn call_expr(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Argument],
+    ) -> Result<Rc<Value>, RloxError> {
         let callee = self.evaluate(callee)?;
 
         let mut args: Vec<Rc<Value>> = vec![];
+        let mut named: Vec<(String, Rc<Value>)> = vec![];
 
         for arg in arguments {
-            args.push(self.evaluate(arg)?);
+            let value = self.evaluate(&arg.value)?;
+            match &arg.name {
+                Some(name) => named.push((name.clone(), value)),
+                None => args.push(value),
+            }
         }
 
-        if let Value::Func(function) = callee.as_ref() {
-            if !arguments.len().eq(&function.arity()) {
-                return Err(RloxError::InterpreterError);
-            }
-            return function.call(self, &args);
-        } else {
-            return Err(RloxError::InterpreterError);
+        let Value::Func(function) = callee.as_ref() else {
+            return Err(RloxError::RuntimeError {
+                lexeme: paren.lexeme.clone(),
+                message: format!("Can only call functions, not a {}.", callee.type_name()),
+            });
+        };
+        // named arguments may stand in for missing positional ones, never for extra ones
+        let arity = function.arity();
+        let too_many = arity.max.is_some_and(|max| args.len() > max);
+        if too_many || (named.is_empty() && !arity.accepts(args.len())) {
+            return Err(RloxError::RuntimeError {
+                lexeme: paren.lexeme.clone(),
+                message: format!("Expected {} arguments but got {}.", arity, args.len()),
+            });
         }
+        self.call_value(function, paren.line, &args, &named)
+    }
+
+    /// Calls a function made on `line`, keeping a frame for it on the stack while it runs.
+    /// Every call goes through here, so none escapes the depth limit or the traces.
+    pub fn call_value(
+        &mut self,
+        function: &Rc<dyn RloxCallable>,
+        line: usize,
+        args: &[Rc<Value>],
+        named: &[(String, Rc<Value>)],
+    ) -> Result<Rc<Value>, RloxError> {
+        if self.frames.len() >= self.max_call_depth {
+            return Err(RloxError::RuntimeError {
+                lexeme: function.to_string(),
+                message: format!("Stack overflow after {} nested calls.", self.frames.len()),
+            });
+        }
+        self.frames.push(CallFrame {
+            function: function.to_string(),
+            line,
+        });
+        let result = nested(|| function.call_named(self, args, named));
+        self.frames.pop();
+        result
+    }
+
+    /// The line the innermost running function was called on, which is where a native
+    /// calls back into the functions it was given.
+    pub fn call_line(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.line)
     }
 }
+
@@ -0,0 +1,128 @@
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io::{stdin, Write};
+use std::rc::Rc;
+
+use crate::callable::*;
+use crate::environment::*;
+use crate::error::*;
+use crate::interpreter::*;
+use crate::natives::{error, expect_number};
+use crate::strings::expect_string;
+
+/// Natives that reach outside the interpreter: files, standard input, the environment
+/// and the process. Only defined for interpreters made with `Interpreter::with_io`.
+pub fn define_io_natives(globals: &Environment) {
+    let natives = [
+        NativeFunction::new("read_line", 0, read_line),
+        NativeFunction::new("read_file", 1, read_file),
+        NativeFunction::new("write_file", 2, write_file),
+        NativeFunction::new("append_file", 2, append_file),
+        NativeFunction::new("list_dir", 1, list_dir),
+        NativeFunction::new("file_exists", 1, file_exists),
+        NativeFunction::new("env", 1, env),
+        NativeFunction::new("args", 0, args),
+        NativeFunction::new("exit", 1, exit),
+    ];
+    for native in natives {
+        globals.define(native.name, Rc::new(Value::Func(Rc::new(native))));
+    }
+}
+
+fn io_error(name: &str, action: &str, path: &str, e: impl Display) -> RloxError {
+    error(name, format!("Can't {} '{}': {}.", action, path, e))
+}
+
+/// The next line of standard input without its line ending, or nil at the end of input.
+fn read_line(interpreter: &mut Interpreter, _args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    // whatever was printed so far is likely the prompt for this line
+    let _ = std::io::stdout().flush();
+    let mut line = String::new();
+    let read = stdin()
+        .read_line(&mut line)
+        .map_err(|e| error("read_line", format!("Can't read from standard input: {}.", e)))?;
+    if read == 0 {
+        return Ok(Rc::new(Value::Nil));
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    interpreter.allocate(Value::Str(line))
+}
+
+fn read_file(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let path = expect_string("read_file", &args[0])?;
+    let contents = fs::read_to_string(path).map_err(|e| io_error("read_file", "read", path, e))?;
+    interpreter.allocate(Value::Str(contents))
+}
+
+fn write_file(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let path = expect_string("write_file", &args[0])?;
+    let contents = expect_string("write_file", &args[1])?;
+    fs::write(path, contents).map_err(|e| io_error("write_file", "write", path, e))?;
+    Ok(Rc::new(Value::Nil))
+}
+
+fn append_file(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let path = expect_string("append_file", &args[0])?;
+    let contents = expect_string("append_file", &args[1])?;
+    fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| io_error("append_file", "append to", path, e))?;
+    Ok(Rc::new(Value::Nil))
+}
+
+/// The names of the entries in a directory, sorted.
+fn list_dir(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let path = expect_string("list_dir", &args[0])?;
+    let mut names = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| io_error("list_dir", "list", path, e))?;
+    names.sort();
+    interpreter.count_allocations(names.len())?;
+    let names = names.into_iter().map(|name| Rc::new(Value::Str(name))).collect();
+    interpreter.allocate(Value::new_list(names))
+}
+
+fn file_exists(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let path = expect_string("file_exists", &args[0])?;
+    Ok(Rc::new(Value::Bool(fs::metadata(path).is_ok())))
+}
+
+/// The value of an environment variable, or nil when it is not set.
+fn env(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let name = expect_string("env", &args[0])?;
+    match env::var(name) {
+        Ok(value) => interpreter.allocate(Value::Str(value)),
+        Err(_) => Ok(Rc::new(Value::Nil)),
+    }
+}
+
+/// A fresh list of the command line arguments after the script, so changing it leaves `argv` alone.
+fn args(interpreter: &mut Interpreter, _args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let args: Vec<_> = interpreter
+        .script_args()
+        .iter()
+        .map(|arg| Rc::new(Value::Str(arg.clone())))
+        .collect();
+    interpreter.count_allocations(args.len())?;
+    interpreter.allocate(Value::new_list(args))
+}
+
+fn exit(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let code = expect_number("exit", &args[0])?;
+    if code.fract() != 0.0 || !(0.0..=255.0).contains(&code) {
+        return Err(error(
+            "exit",
+            format!("Exit code {} is not a whole number from 0 to 255.", args[0]),
+        ));
+    }
+    Err(RloxError::Exit(code as i32))
+}
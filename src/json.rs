@@ -0,0 +1,533 @@
+use std::rc::Rc;
+
+use crate::callable::*;
+use crate::environment::*;
+use crate::error::*;
+use crate::interpreter::*;
+use crate::map::*;
+use crate::natives::{error, expect_number};
+use crate::strings::expect_string;
+
+pub fn define_json_natives(globals: &Environment) {
+    let natives = [
+        NativeFunction::new("json_parse", 1, json_parse),
+        NativeFunction::with_arity("json_stringify", Arity::range(1, 2), json_stringify),
+    ];
+    for native in natives {
+        globals.define(native.name, Rc::new(Value::Func(Rc::new(native))));
+    }
+}
+
+/// Objects become maps, arrays lists, and null nil.
+fn json_parse(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let text = expect_string("json_parse", &args[0])?;
+    let mut parser = JsonParser {
+        chars: text.chars().collect(),
+        current: 0,
+        allocations: 0,
+    };
+    parser.skip_whitespace();
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if !parser.is_end() {
+        return Err(parser.error("Unexpected text after the JSON value"));
+    }
+    interpreter.count_allocations(parser.allocations)?;
+    Ok(value)
+}
+
+/// `json_stringify(value)` writes compact JSON; `json_stringify(value, 2)` puts every
+/// element on its own line, indented by that many spaces per level.
+fn json_stringify(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let indent = match args.get(1) {
+        Some(indent) => {
+            let n = expect_number("json_stringify", indent)?;
+            if n.fract() != 0.0 || !(0.0..=16.0).contains(&n) {
+                return Err(error(
+                    "json_stringify",
+                    format!("Indent {} is not a whole number from 0 to 16.", indent),
+                ));
+            }
+            Some(" ".repeat(n as usize))
+        }
+        None => None,
+    };
+    let mut writer = JsonWriter {
+        output: String::new(),
+        indent,
+        path: vec![],
+    };
+    writer.value(&args[0], 0)?;
+    interpreter.allocate(Value::Str(writer.output))
+}
+
+/// How deep arrays and objects may nest in JSON, as in serde_json. Reading and writing
+/// recurse once per level, and debug builds use a few KB of stack for each.
+const MAX_NESTING: usize = 128;
+
+struct JsonParser {
+    chars: Vec<char>,
+    current: usize,
+    /// The lists, maps and strings created so far.
+    allocations: usize,
+}
+
+impl JsonParser {
+    fn error(&self, message: &str) -> RloxError {
+        let before = &self.chars[..self.current.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        error(
+            "json_parse",
+            format!("{} at line {}, column {}.", message, line, column),
+        )
+    }
+
+    fn is_end(&self) -> bool {
+        self.current >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.current += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.current += 1;
+        }
+    }
+
+    fn consume(&mut self, expected: char) -> Result<(), RloxError> {
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", expected)))
+        }
+    }
+
+    /// A value nested inside `depth` arrays and objects.
+    fn value(&mut self, depth: usize) -> Result<Rc<Value>, RloxError> {
+        if matches!(self.peek(), Some('{' | '[')) && depth >= MAX_NESTING {
+            return Err(self.error(&format!("JSON nested more than {} deep", MAX_NESTING)));
+        }
+        if matches!(self.peek(), Some('{' | '[' | '"')) {
+            self.allocations += 1;
+        }
+        let value = match self.peek() {
+            Some('{') => self.object(depth)?,
+            Some('[') => self.array(depth)?,
+            Some('"') => Value::Str(self.string()?),
+            Some('-' | '0'..='9') => self.number()?,
+            Some('t') => self.word("true", Value::Bool(true))?,
+            Some('f') => self.word("false", Value::Bool(false))?,
+            Some('n') => self.word("null", Value::Nil)?,
+            Some(_) => return Err(self.error("Expected a JSON value")),
+            None => return Err(self.error("Unexpected end of JSON")),
+        };
+        Ok(Rc::new(value))
+    }
+
+    fn word(&mut self, word: &str, value: Value) -> Result<Value, RloxError> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error("Expected a JSON value"));
+            }
+            self.current += 1;
+        }
+        Ok(value)
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, RloxError> {
+        self.consume('{')?;
+        let mut map = RloxMap::default();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+            return Ok(Value::new_map(map));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected a string key"));
+            }
+            let key = self.string()?;
+            self.allocations += 1;
+            self.skip_whitespace();
+            self.consume(':')?;
+            self.skip_whitespace();
+            let value = self.value(depth + 1)?;
+            map.insert(Rc::new(Value::Str(key)), value, "json_parse")?;
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::new_map(map)),
+                _ => {
+                    self.current -= 1;
+                    return Err(self.error("Expected ',' or '}'"));
+                }
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, RloxError> {
+        self.consume('[')?;
+        let mut elements = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+            return Ok(Value::new_list(elements));
+        }
+        loop {
+            self.skip_whitespace();
+            elements.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::new_list(elements)),
+                _ => {
+                    self.current -= 1;
+                    return Err(self.error("Expected ',' or ']'"));
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, RloxError> {
+        self.consume('"')?;
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let escaped = match self.advance() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => {
+                            self.current -= 1;
+                            return Err(self.error("Invalid escape sequence"));
+                        }
+                    };
+                    s.push(escaped);
+                }
+                Some(c) if c < ' ' => {
+                    self.current -= 1;
+                    return Err(self.error("Unescaped control character in string"));
+                }
+                Some(c) => s.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    /// The character after `\u`, joining a surrogate pair written as two escapes.
+    fn unicode_escape(&mut self) -> Result<char, RloxError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("Invalid unicode escape"));
+        }
+        if self.peek() != Some('\\') || self.chars.get(self.current + 1) != Some(&'u') {
+            return Err(self.error("Unpaired surrogate in unicode escape"));
+        }
+        self.current += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("Unpaired surrogate in unicode escape"));
+        }
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, RloxError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .peek()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("Expected four hex digits"))?;
+            code = code * 16 + digit;
+            self.current += 1;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, RloxError> {
+        let start = self.current;
+        if self.peek() == Some('-') {
+            self.current += 1;
+        }
+        match self.peek() {
+            Some('0') => self.current += 1,
+            Some('1'..='9') => self.digits(),
+            _ => return Err(self.error("Expected a digit")),
+        }
+        if self.peek() == Some('.') {
+            self.current += 1;
+            if !matches!(self.peek(), Some('0'..='9')) {
+                return Err(self.error("Expected a digit after '.'"));
+            }
+            self.digits();
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.current += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.current += 1;
+            }
+            if !matches!(self.peek(), Some('0'..='9')) {
+                return Err(self.error("Expected a digit in the exponent"));
+            }
+            self.digits();
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        Ok(Value::Number(text.parse().expect("checked to be a JSON number")))
+    }
+
+    fn digits(&mut self) {
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.current += 1;
+        }
+    }
+}
+
+struct JsonWriter {
+    output: String,
+    indent: Option<String>,
+    /// The lists and maps being written, to catch one that contains itself.
+    path: Vec<*const ()>,
+}
+
+impl JsonWriter {
+    fn value(&mut self, value: &Rc<Value>, depth: usize) -> Result<(), RloxError> {
+        match value.as_ref() {
+            Value::Nil => self.output.push_str("null"),
+            Value::Bool(b) => self.output.push_str(&b.to_string()),
+            Value::Number(n) if n.is_finite() => self.output.push_str(&value.to_string()),
+            Value::Number(n) => {
+                return Err(error(
+                    "json_stringify",
+                    format!("JSON has no way to write the number {}.", n),
+                ))
+            }
+            Value::Str(s) => self.string(s),
+            Value::List(list) => {
+                self.enter(value, Rc::as_ptr(list) as *const ())?;
+                let elements = list.borrow().clone();
+                self.output.push('[');
+                for (i, element) in elements.iter().enumerate() {
+                    self.separator(i, depth + 1);
+                    self.value(element, depth + 1)?;
+                }
+                self.close(elements.is_empty(), depth, ']');
+                self.path.pop();
+            }
+            Value::Map(map) => {
+                self.enter(value, Rc::as_ptr(map) as *const ())?;
+                let entries: Vec<_> = map.borrow().entries().cloned().collect();
+                self.output.push('{');
+                for (i, (key, element)) in entries.iter().enumerate() {
+                    let Value::Str(key) = key.as_ref() else {
+                        return Err(error(
+                            "json_stringify",
+                            format!("JSON object keys must be strings, not a {}.", key.type_name()),
+                        ));
+                    };
+                    self.separator(i, depth + 1);
+                    self.string(key);
+                    self.output.push(':');
+                    if self.indent.is_some() {
+                        self.output.push(' ');
+                    }
+                    self.value(element, depth + 1)?;
+                }
+                self.close(entries.is_empty(), depth, '}');
+                self.path.pop();
+            }
+            Value::Func(_) | Value::Module(_) => {
+                return Err(error(
+                    "json_stringify",
+                    format!("Can't convert a {} to JSON.", value.type_name()),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts writing `value`, the list or map at `pointer`.
+    fn enter(&mut self, value: &Value, pointer: *const ()) -> Result<(), RloxError> {
+        if self.path.len() >= MAX_NESTING {
+            return Err(error(
+                "json_stringify",
+                format!(
+                    "Can't convert a {} nested more than {} deep to JSON.",
+                    value.type_name(),
+                    MAX_NESTING
+                ),
+            ));
+        }
+        if self.path.contains(&pointer) {
+            return Err(error(
+                "json_stringify",
+                format!("Can't convert a {} that contains itself to JSON.", value.type_name()),
+            ));
+        }
+        self.path.push(pointer);
+        Ok(())
+    }
+
+    /// The comma before every element but the first, and the line break before each one
+    /// when indenting.
+    fn separator(&mut self, index: usize, depth: usize) {
+        if index > 0 {
+            self.output.push(',');
+        }
+        self.newline(depth);
+    }
+
+    fn close(&mut self, empty: bool, depth: usize, bracket: char) {
+        if !empty {
+            self.newline(depth);
+        }
+        self.output.push(bracket);
+    }
+
+    fn newline(&mut self, depth: usize) {
+        if let Some(indent) = &self.indent {
+            self.output.push('\n');
+            self.output.push_str(&indent.repeat(depth));
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.output.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.output.push_str("\\\""),
+                '\\' => self.output.push_str("\\\\"),
+                '\n' => self.output.push_str("\\n"),
+                '\r' => self.output.push_str("\\r"),
+                '\t' => self.output.push_str("\\t"),
+                c if c < ' ' => self.output.push_str(&format!("\\u{:04x}", c as u32)),
+                c => self.output.push(c),
+            }
+        }
+        self.output.push('"');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn parse(text: &str) -> Result<Rc<Value>, RloxError> {
+        json_parse(&mut Interpreter::new(), &[Rc::new(Value::Str(text.to_string()))])
+    }
+
+    fn stringify(value: Rc<Value>, indent: Option<f64>) -> Result<String, RloxError> {
+        let mut args = vec![value];
+        args.extend(indent.map(|indent| Rc::new(Value::Number(indent))));
+        match json_stringify(&mut Interpreter::new(), &args)?.as_ref() {
+            Value::Str(s) => Ok(s.clone()),
+            value => panic!("json_stringify returned a {}", value.type_name()),
+        }
+    }
+
+    fn message(error: RloxError) -> String {
+        match error {
+            RloxError::RuntimeError { message, .. } => message,
+            error => panic!("expected a runtime error, not {:?}", error),
+        }
+    }
+
+    #[test]
+    fn parsed_json_stringifies_to_json_that_parses_the_same() {
+        let text = r#" {"a": [1, 2.5, -3e2, true, false, null], "b": {"c": "d"}, "e": []} "#;
+        let value = parse(text).unwrap();
+        let json = stringify(value.clone(), None).unwrap();
+        assert_eq!(json, r#"{"a":[1,2.5,-300,true,false,null],"b":{"c":"d"},"e":[]}"#);
+        assert_eq!(parse(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn escapes_are_read_and_written() {
+        let value = parse(r#""q\"b\\s\/n\nt\tué\u0001""#).unwrap();
+        assert_eq!(*value, Value::Str("q\"b\\s/n\nt\tu\u{e9}\u{1}".to_string()));
+        assert_eq!(stringify(value, None).unwrap(), r#""q\"b\\s/n\nt\tué\u0001""#);
+        assert!(message(parse(r#""\x""#).unwrap_err()).starts_with("Invalid escape sequence"));
+    }
+
+    #[test]
+    fn surrogate_pairs_join_into_one_character() {
+        assert_eq!(*parse(r#""😀""#).unwrap(), Value::Str("\u{1f600}".to_string()));
+        assert!(message(parse(r#""\ud83d""#).unwrap_err()).starts_with("Unpaired surrogate"));
+        assert!(message(parse(r#""\ud83dA""#).unwrap_err()).starts_with("Unpaired surrogate"));
+        assert!(message(parse(r#""\ude00""#).unwrap_err()).starts_with("Invalid unicode escape"));
+    }
+
+    #[test]
+    fn an_indent_puts_every_element_on_its_own_line() {
+        let value = parse(r#"{"a":[1,2],"b":{},"c":[]}"#).unwrap();
+        assert_eq!(
+            stringify(value, Some(2.0)).unwrap(),
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {},\n  \"c\": []\n}"
+        );
+    }
+
+    #[test]
+    fn functions_are_not_json() {
+        let function = Rc::new(Value::Func(Rc::new(NativeFunction::new("f", 0, json_parse))));
+        let list = Rc::new(Value::new_list(vec![function]));
+        assert_eq!(message(stringify(list, None).unwrap_err()), "Can't convert a native to JSON.");
+    }
+
+    #[test]
+    fn a_list_inside_itself_is_not_json_even_behind_another_rc() {
+        let elements = Rc::new(RefCell::new(vec![]));
+        let list = Rc::new(Value::List(elements.clone()));
+        elements.borrow_mut().push(Rc::new(Value::List(elements.clone())));
+        assert_eq!(
+            message(stringify(list, None).unwrap_err()),
+            "Can't convert a list that contains itself to JSON."
+        );
+
+        // the same list twice side by side is no cycle
+        let inner = Rc::new(Value::new_list(vec![]));
+        let twice = Rc::new(Value::new_list(vec![inner.clone(), inner]));
+        assert_eq!(stringify(twice, None).unwrap(), "[[],[]]");
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let deepest = "[".repeat(MAX_NESTING) + &"]".repeat(MAX_NESTING);
+        assert!(parse(&deepest).is_ok());
+        let too_deep = "[".repeat(100_000);
+        let parse_error = message(parse(&too_deep).unwrap_err());
+        let expected = format!("JSON nested more than {} deep", MAX_NESTING);
+        assert!(parse_error.starts_with(&expected), "{}", parse_error);
+
+        let mut list = Rc::new(Value::new_list(vec![]));
+        for _ in 0..MAX_NESTING - 1 {
+            list = Rc::new(Value::new_list(vec![list]));
+        }
+        assert_eq!(stringify(list.clone(), None).unwrap(), deepest);
+        let too_deep = Rc::new(Value::new_list(vec![list]));
+        assert_eq!(
+            message(stringify(too_deep, None).unwrap_err()),
+            format!("Can't convert a list nested more than {} deep to JSON.", MAX_NESTING)
+        );
+    }
+}
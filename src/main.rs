@@ -1,87 +1,427 @@
 mod environment;
 mod error;
 mod interpreter;
+mod io;
+mod json;
 mod parser;
 mod scanner;
 mod stmt;
+mod strings;
 mod callable;
 mod resolver;
 mod expr;
+mod map;
+mod math;
+mod module;
+mod natives;
+mod repl;
+mod session;
+mod ast_printer;
+mod budget;
+mod pattern;
 
-use error::RloxError;
+use error::{ErrorCategory, RloxError};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
 use std::{
-    env::args,
+    env::{args, var, var_os},
     fs::read_to_string,
-    io::{stdin, stdout, Write},
+    io::{stdin, Read},
+    path::{Path, PathBuf},
     process::exit,
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
-use crate::interpreter::*;
-use crate::parser::*;
-use crate::resolver::*;
+use crate::ast_printer::*;
+use crate::budget::{Budget, CancelHandle};
+use crate::interpreter::DEFAULT_MAX_CALL_DEPTH;
+use crate::repl::*;
+use crate::session::*;
+
+// Exit codes follow the sysexits(3) conventions.
+const EX_OK: i32 = 0;
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+
+/// The deepest `RLOX_MAX_CALL_DEPTH` allowed. Each nested call takes a few KB of stack,
+/// tens in debug builds, so this already lets a script use gigabytes.
+const MAX_CALL_DEPTH: usize = 100_000;
+
+const USAGE: &str = "\
+Usage: rlox [script|-] [args...]
+       rlox run <script|-> [args...]
+       rlox run -e <code> [args...]
+       rlox check <script|->
+       rlox check -e <code>
+       rlox repl
+
+Commands:
+  run      Execute a script ('-' reads it from stdin); the arguments
+           after it are available to the script as the list argv
+  check    Scan, parse and resolve a script without running it
+  repl     Start an interactive prompt (the default without arguments)
+
+Options:
+  -e <code>    Evaluate <code> instead of reading a script
+  -h, --help   Print this message
+
+Exit codes:
+  0    Success
+  64   Invalid command line usage
+  65   The script failed to scan, parse or resolve
+  66   The script could not be read
+  70   The script failed at runtime
+
+Environment:
+  RLOX_HISTORY          REPL history file (defaults to ~/.rlox_history)
+  RLOX_MAX_CALL_DEPTH   How deep calls may nest before a script fails with
+                        a stack overflow error (defaults to 1000, at
+                        most 100000)
+  RLOX_MAX_STEPS        Stop a run after this many statements and expressions
+  RLOX_MAX_ALLOCATIONS  Stop a run after it creates this many lists, maps,
+                        strings and functions
+  RLOX_TIMEOUT          Stop a run after this many seconds";
 
 struct Rlox {
-    interpreter: Interpreter,
+    session: Session,
 }
 
 impl Rlox {
     pub fn new() -> Self {
         Self {
-            interpreter: Interpreter::new(),
+            session: Session::with_io(),
         }
     }
-    pub fn run_file(&mut self, path: &str) -> std::io::Result<()> {
 
-        let file = read_to_string(path)?;
-        match self.run(&file) {
-            Ok(_) => {}
-            Err(e) => {
-                e.report();
+    pub fn run_prompt(&mut self) -> rustyline::Result<()> {
+        let mut editor: Editor<RloxHelper, DefaultHistory> = Editor::new()?;
+        editor.set_helper(Some(RloxHelper::new(self.session.interpreter().globals())));
+        let history = history_path();
+        if let Some(path) = &history {
+            // a missing history file just means this is the first session
+            let _ = editor.load_history(path);
+        }
+
+        let cancel = self.session.interpreter().cancel_handle();
+        cancel_on_interrupt(cancel.clone());
+
+        let mut source = String::new();
+        let mut exit_status = None;
+        loop {
+            let prompt = if source.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            match editor.readline(prompt) {
+                Ok(line) => {
+                    // a Ctrl-C that came after the last entry finished stops nothing
+                    cancel.reset();
+                    source.push_str(&line);
+                    source.push('\n');
+                }
+                Err(ReadlineError::Interrupted) => {
+                    source.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e),
+            }
+            if source.trim().is_empty() {
+                source.clear();
+                continue;
+            }
+
+            if source.trim_start().starts_with(':') {
+                editor.add_history_entry(source.trim())?;
+                match MetaCommand::parse(&source) {
+                    Ok(MetaCommand::Quit) => break,
+                    Ok(MetaCommand::Reset) => {
+                        self.session.reset();
+                        editor.set_helper(Some(RloxHelper::new(self.session.interpreter().globals())));
+                    }
+                    Ok(command) => self.meta_command(command),
+                    Err(message) => eprintln!("{}", message),
+                }
+                source.clear();
+                continue;
+            }
+            if !is_expression(&source) && is_incomplete(&source) {
+                continue;
+            }
+
+            editor.add_history_entry(source.trim_end())?;
+            match self.run_entry(&source) {
+                Err(RloxError::Exit(code)) => {
+                    exit_status = Some(code);
+                    break;
+                }
+                Err(e) => e.report(),
+                Ok(()) => {}
             }
+            source.clear();
+        }
+
+        if let Some(path) = &history {
+            editor.save_history(path)?;
+        }
+        if let Some(code) = exit_status {
+            exit(code);
         }
         Ok(())
     }
 
-    pub fn run_prompt(&mut self) -> std::io::Result<()> {
-        Ok(loop {
-            print!("> ");
-            stdout().flush()?;
-            let mut line = String::new();
-            stdin().read_line(&mut line)?;
-            if line.trim().is_empty() {
-                break;
+    fn meta_command(&mut self, command: MetaCommand) {
+        let result = match command {
+            MetaCommand::Env => {
+                let globals = self.session.interpreter().globals();
+                let globals = globals.borrow();
+                let mut names = globals.names();
+                names.sort();
+                for name in names {
+                    if let Ok(value) = globals.get(&name) {
+                        println!("{} = {}", name, value);
+                    }
+                }
+                Ok(())
             }
-            match self.run(&line) {
-                Ok(_) => {}
+            MetaCommand::Type(source) => self
+                .session
+                .evaluate(&source)
+                .map(|value| println!("{}", value.type_name())),
+            MetaCommand::Ast(source) => self
+                .session
+                .parse_expression(&source)
+                .map(|expr| println!("{}", AstPrinter {}.print(&expr))),
+            MetaCommand::Load(path) => match read_to_string(&path) {
+                Ok(source) => self.session.run(&source),
                 Err(e) => {
-                    e.report();
+                    eprintln!("cannot read {}: {}", path, e);
+                    Ok(())
                 }
+            },
+            MetaCommand::Time(source) => {
+                let start = Instant::now();
+                let result = self.run_entry(&source);
+                eprintln!("took {:?}", start.elapsed());
+                result
+            }
+            MetaCommand::Help => {
+                println!("{}", META_HELP);
+                Ok(())
+            }
+            MetaCommand::Reset | MetaCommand::Quit => Ok(()),
+        };
+        if let Err(e) = result {
+            e.report();
+        }
+    }
+
+    /// Runs one prompt entry, printing the value when it is a bare expression.
+    fn run_entry(&mut self, source: &str) -> Result<(), RloxError> {
+        if is_expression(source) {
+            let value = self.session.evaluate(source)?;
+            println!("{}", value);
+            Ok(())
+        } else {
+            self.session.run(source)
+        }
+    }
+}
+
+enum Source {
+    File(String),
+    Stdin,
+    Inline(String),
+}
+
+impl Source {
+    fn read(&self) -> std::io::Result<String> {
+        match self {
+            Source::File(path) => read_to_string(path),
+            Source::Stdin => {
+                let mut source = String::new();
+                stdin().read_to_string(&mut source)?;
+                Ok(source)
+            }
+            Source::Inline(code) => Ok(code.clone()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Source::File(path) => path,
+            Source::Stdin => "<stdin>",
+            Source::Inline(_) => "<inline>",
+        }
+    }
+}
+
+enum Command {
+    Run { source: Source, args: Vec<String> },
+    Check { source: Source },
+    Repl,
+    Help,
+}
+
+fn parse_source(args: &[String]) -> Result<(Source, &[String]), String> {
+    match args {
+        [] => Err("expected a script, '-' or -e <code>".to_string()),
+        [flag] if flag == "-e" => Err("-e requires an argument".to_string()),
+        [flag, code, rest @ ..] if flag == "-e" => Ok((Source::Inline(code.clone()), rest)),
+        [path, rest @ ..] if path == "-" => Ok((Source::Stdin, rest)),
+        [path, _rest @ ..] if path.starts_with('-') => Err(format!("unknown option '{}'", path)),
+        [path, rest @ ..] => Ok((Source::File(path.clone()), rest)),
+    }
+}
+
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    match args {
+        [] => Ok(Command::Repl),
+        [flag, ..] if flag == "-h" || flag == "--help" => Ok(Command::Help),
+        [command, rest @ ..] if command == "repl" => {
+            if rest.is_empty() {
+                Ok(Command::Repl)
+            } else {
+                Err("repl takes no arguments".to_string())
+            }
+        }
+        [command, rest @ ..] if command == "check" => {
+            let (source, rest) = parse_source(rest)?;
+            if rest.is_empty() {
+                Ok(Command::Check { source })
+            } else {
+                Err("check takes a single script".to_string())
             }
+        }
+        [command, rest @ ..] if command == "run" => {
+            let (source, rest) = parse_source(rest)?;
+            Ok(Command::Run {
+                source,
+                args: rest.to_vec(),
+            })
+        }
+        _ => {
+            let (source, rest) = parse_source(args)?;
+            Ok(Command::Run {
+                source,
+                args: rest.to_vec(),
+            })
+        }
+    }
+}
+
+fn exit_code(error: &RloxError) -> i32 {
+    if let RloxError::Exit(code) = error {
+        return *code;
+    }
+    match error.category() {
+        ErrorCategory::Compile => EX_DATAERR,
+        ErrorCategory::Runtime => EX_SOFTWARE,
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    var_os("RLOX_HISTORY")
+        .map(PathBuf::from)
+        .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".rlox_history")))
+}
+
+fn read_source(source: &Source) -> String {
+    source.read().unwrap_or_else(|e| {
+        eprintln!("rlox: cannot read {}: {}", source.name(), e);
+        exit(EX_NOINPUT);
+    })
+}
+
+/// A limit read from the environment variable `name`, if it is set.
+fn env_limit<T: FromStr>(name: &str, what: &str) -> Option<T> {
+    let value = var(name).ok()?;
+    Some(value.parse().unwrap_or_else(|_| {
+        eprintln!("rlox: {} must be {}, not '{}'", name, what, value);
+        exit(EX_USAGE);
+    }))
+}
+
+fn budget() -> Budget {
+    let timeout = env_limit::<f64>("RLOX_TIMEOUT", "a number of seconds").map(|seconds| {
+        Duration::try_from_secs_f64(seconds).unwrap_or_else(|_| {
+            eprintln!("rlox: RLOX_TIMEOUT must be a number of seconds, not '{}'", seconds);
+            exit(EX_USAGE);
         })
+    });
+    Budget {
+        max_steps: env_limit("RLOX_MAX_STEPS", "a whole number"),
+        deadline: timeout.and_then(|timeout| Instant::now().checked_add(timeout)),
+        max_allocations: env_limit("RLOX_MAX_ALLOCATIONS", "a whole number"),
     }
-    pub fn run(&mut self, source: &str) -> Result<(), RloxError> {
-        let scanner = scanner::Scanner::default().scan_tokens(source.to_string())?;
-        let mut parser = Parser {
-            tokens: scanner.to_vec(),
-            current: 0,
-        };
-        let statements = parser.parse()?;
+}
+
+/// Makes Ctrl-C stop the code running at the prompt rather than the whole REPL. While
+/// reading a line the editor handles Ctrl-C itself.
+#[cfg(unix)]
+fn cancel_on_interrupt(cancel: CancelHandle) {
+    use signal_hook::{consts::SIGINT, iterator::Signals};
 
-        let mut resolver = Resolver::new(self.interpreter.clone());
-        resolver.resolve(&statements)?;
-        resolver.interpreter.interpret(statements)
+    // without the handler Ctrl-C still works, it just ends the REPL too
+    let Ok(mut signals) = Signals::new([SIGINT]) else {
+        return;
+    };
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            cancel.cancel();
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn cancel_on_interrupt(_cancel: CancelHandle) {}
+
+fn max_call_depth() -> usize {
+    let depth =
+        env_limit("RLOX_MAX_CALL_DEPTH", "a whole number").unwrap_or(DEFAULT_MAX_CALL_DEPTH);
+    if depth > MAX_CALL_DEPTH {
+        eprintln!("rlox: RLOX_MAX_CALL_DEPTH must be at most {}, not '{}'", MAX_CALL_DEPTH, depth);
+        exit(EX_USAGE);
     }
+    depth
 }
 
 fn main() -> std::io::Result<()> {
+    let args: Vec<_> = args().skip(1).collect();
+    let command = parse_command(&args).unwrap_or_else(|message| {
+        eprintln!("rlox: {}\n\n{}", message, USAGE);
+        exit(EX_USAGE);
+    });
+
     let mut rlox = Rlox::new();
-    let args: Vec<_> = args().collect();
-    if args.len() > 2 {
-        println!("Usage: rlox [script]");
-        exit(64);
-    } else if args.len() == 2 {
-        rlox.run_file(&args[1])
-    } else {
-        rlox.run_prompt()
+    rlox.session.set_max_call_depth(max_call_depth());
+    let result = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Repl => return rlox.run_prompt().map_err(std::io::Error::other),
+        Command::Check { source } => rlox.session.check(&read_source(&source)),
+        Command::Run { source, args } => {
+            if let Source::File(path) = &source {
+                rlox.session.set_script_path(Path::new(path));
+            }
+            rlox.session.set_script_args(&args);
+            rlox.session.set_budget(budget());
+            rlox.session.run(&read_source(&source))
+        }
+    };
+
+    match result {
+        Ok(()) => exit(EX_OK),
+        Err(e) => {
+            e.report();
+            exit(exit_code(&e));
+        }
     }
 }
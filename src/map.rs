@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::*;
+use crate::interpreter::*;
+
+/// The hashable form of a value usable as a map key.
+///
+/// Keys hash the same way `PartialEq for Value` compares: `0` and `-0` are one key,
+/// and NaN is rejected because it is not equal to itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MapKey {
+    Str(String),
+    Number(u64),
+    Bool(bool),
+    Nil,
+}
+
+impl MapKey {
+    fn from_value(value: &Value, lexeme: &str) -> Result<MapKey, RloxError> {
+        match value {
+            Value::Str(s) => Ok(MapKey::Str(s.clone())),
+            Value::Number(n) if n.is_nan() => Err(RloxError::RuntimeError {
+                lexeme: lexeme.to_string(),
+                message: "NaN can't be used as a map key.".to_string(),
+            }),
+            Value::Number(n) if *n == 0.0 => Ok(MapKey::Number(0f64.to_bits())),
+            Value::Number(n) => Ok(MapKey::Number(n.to_bits())),
+            Value::Bool(b) => Ok(MapKey::Bool(*b)),
+            Value::Nil => Ok(MapKey::Nil),
+            _ => Err(RloxError::RuntimeError {
+                lexeme: lexeme.to_string(),
+                message: format!("A {} can't be used as a map key.", value.type_name()),
+            }),
+        }
+    }
+}
+
+/// A hash map that remembers the order its keys were first inserted in.
+#[derive(Debug, Clone, Default)]
+pub struct RloxMap {
+    entries: Vec<(Rc<Value>, Rc<Value>)>,
+    index: HashMap<MapKey, usize>,
+}
+
+impl RloxMap {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: &Value, lexeme: &str) -> Result<Option<Rc<Value>>, RloxError> {
+        let key = MapKey::from_value(key, lexeme)?;
+        Ok(self.index.get(&key).map(|&i| self.entries[i].1.clone()))
+    }
+
+    pub fn insert(&mut self, key: Rc<Value>, value: Rc<Value>, lexeme: &str) -> Result<(), RloxError> {
+        let map_key = MapKey::from_value(&key, lexeme)?;
+        match self.index.get(&map_key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.index.insert(map_key, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether both maps hold the same keys, with values that `eq` finds equal.
+    pub fn eq_by(&self, other: &RloxMap, mut eq: impl FnMut(&Value, &Value) -> bool) -> bool {
+        self.len() == other.len()
+            && self.index.iter().all(|(key, &i)| match other.index.get(key) {
+                Some(&j) => eq(&self.entries[i].1, &other.entries[j].1),
+                None => false,
+            })
+    }
+
+    pub fn remove(&mut self, key: &Value, lexeme: &str) -> Result<Option<Rc<Value>>, RloxError> {
+        let key = MapKey::from_value(key, lexeme)?;
+        let Some(i) = self.index.remove(&key) else {
+            return Ok(None);
+        };
+        let (_, value) = self.entries.remove(i);
+        for position in self.index.values_mut() {
+            if *position > i {
+                *position -= 1;
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// The entries in insertion order.
+    pub fn entries(&self) -> impl Iterator<Item = &(Rc<Value>, Rc<Value>)> {
+        self.entries.iter()
+    }
+
+    /// Empties the map, handing back its keys and values.
+    pub fn take_entries(&mut self) -> Vec<(Rc<Value>, Rc<Value>)> {
+        self.index.clear();
+        std::mem::take(&mut self.entries)
+    }
+}
+
+impl PartialEq for RloxMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_by(other, |a, b| a == b)
+    }
+}
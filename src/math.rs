@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+use std::f64::consts;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::callable::*;
+use crate::environment::*;
+use crate::error::*;
+use crate::interpreter::*;
+use crate::module::*;
+use crate::natives::{error, expect_number};
+
+/// The builtin `math` module.
+pub fn math_module() -> RloxModule {
+    let globals = Environment::default();
+    let natives = [
+        NativeFunction::new("sqrt", 1, |_, args| unary("sqrt", args, f64::sqrt)),
+        NativeFunction::new("abs", 1, |_, args| unary("abs", args, f64::abs)),
+        NativeFunction::new("floor", 1, |_, args| unary("floor", args, f64::floor)),
+        NativeFunction::new("ceil", 1, |_, args| unary("ceil", args, f64::ceil)),
+        NativeFunction::new("round", 1, |_, args| unary("round", args, f64::round)),
+        NativeFunction::new("sin", 1, |_, args| unary("sin", args, f64::sin)),
+        NativeFunction::new("cos", 1, |_, args| unary("cos", args, f64::cos)),
+        NativeFunction::new("tan", 1, |_, args| unary("tan", args, f64::tan)),
+        NativeFunction::new("asin", 1, |_, args| unary("asin", args, f64::asin)),
+        NativeFunction::new("acos", 1, |_, args| unary("acos", args, f64::acos)),
+        NativeFunction::new("atan", 1, |_, args| unary("atan", args, f64::atan)),
+        NativeFunction::new("exp", 1, |_, args| unary("exp", args, f64::exp)),
+        NativeFunction::new("pow", 2, pow),
+        NativeFunction::new("atan2", 2, atan2),
+        NativeFunction::with_arity("log", Arity::range(1, 2), log),
+        NativeFunction::with_arity("min", Arity { min: 1, max: None }, min),
+        NativeFunction::with_arity("max", Arity { min: 1, max: None }, max),
+        NativeFunction::new("is_nan", 1, is_nan),
+        NativeFunction::new("seed", 1, seed),
+        NativeFunction::new("random", 0, random),
+        NativeFunction::new("random_int", 2, random_int),
+    ];
+    for native in natives {
+        globals.define(native.name, Rc::new(Value::Func(Rc::new(native))));
+    }
+    globals.define("PI", Rc::new(Value::Number(consts::PI)));
+    globals.define("E", Rc::new(Value::Number(consts::E)));
+
+    RloxModule {
+        name: "math".to_string(),
+        globals: Rc::new(RefCell::new(globals)),
+    }
+}
+
+fn unary(name: &str, args: &[Rc<Value>], function: fn(f64) -> f64) -> Result<Rc<Value>, RloxError> {
+    let n = expect_number(name, &args[0])?;
+    Ok(Rc::new(Value::Number(function(n))))
+}
+
+fn pow(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let base = expect_number("pow", &args[0])?;
+    let exponent = expect_number("pow", &args[1])?;
+    Ok(Rc::new(Value::Number(base.powf(exponent))))
+}
+
+fn atan2(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let y = expect_number("atan2", &args[0])?;
+    let x = expect_number("atan2", &args[1])?;
+    Ok(Rc::new(Value::Number(y.atan2(x))))
+}
+
+/// `log(x)` is the natural logarithm, `log(x, base)` the one in the given base.
+fn log(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let n = expect_number("log", &args[0])?;
+    let result = match args.get(1) {
+        Some(base) => n.log(expect_number("log", base)?),
+        None => n.ln(),
+    };
+    Ok(Rc::new(Value::Number(result)))
+}
+
+fn min(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    fold("min", args, f64::min)
+}
+
+fn max(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    fold("max", args, f64::max)
+}
+
+fn fold(name: &str, args: &[Rc<Value>], function: fn(f64, f64) -> f64) -> Result<Rc<Value>, RloxError> {
+    let mut result = expect_number(name, &args[0])?;
+    for arg in &args[1..] {
+        result = function(result, expect_number(name, arg)?);
+    }
+    Ok(Rc::new(Value::Number(result)))
+}
+
+fn is_nan(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let n = expect_number("is_nan", &args[0])?;
+    Ok(Rc::new(Value::Bool(n.is_nan())))
+}
+
+fn seed(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let seed = expect_number("seed", &args[0])?;
+    if seed.fract() != 0.0 || seed.is_nan() {
+        return Err(error("seed", format!("The seed must be a whole number, not {}.", seed)));
+    }
+    *interpreter.rng() = Rng::new(seed as i64 as u64);
+    Ok(Rc::new(Value::Nil))
+}
+
+fn random(interpreter: &mut Interpreter, _args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    Ok(Rc::new(Value::Number(interpreter.rng().next_f64())))
+}
+
+/// A whole number between `low` and `high`, both included.
+fn random_int(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let low = expect_number("random_int", &args[0])?.ceil();
+    let high = expect_number("random_int", &args[1])?.floor();
+    if low > high || low.is_nan() || high.is_nan() {
+        return Err(error(
+            "random_int",
+            format!("There is no whole number between {} and {}.", args[0], args[1]),
+        ));
+    }
+    let span = (high - low + 1.0) as u64;
+    let offset = interpreter.rng().next_u64() % span;
+    Ok(Rc::new(Value::Number(low + offset as f64)))
+}
+
+/// SplitMix64: small, fast and the same sequence for the same seed on every platform.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeded from the clock, for scripts that never call `math.seed`.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,20 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::environment::*;
+
+/// An imported source file, known by the globals its top-level code left behind.
+#[derive(Debug)]
+pub struct RloxModule {
+    pub name: String,
+    pub globals: Rc<RefCell<Environment>>,
+}
+
+/// The top level code runs against: the globals of the main script or of a module, and
+/// the file they came from, which relative imports start from.
+#[derive(Debug, Clone)]
+pub struct ModuleScope {
+    pub globals: Rc<RefCell<Environment>>,
+    pub file: Option<Rc<Path>>,
+}
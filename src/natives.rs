@@ -0,0 +1,299 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::callable::*;
+use crate::environment::*;
+use crate::error::*;
+use crate::interpreter::*;
+use crate::map::*;
+use crate::strings::parse_number;
+
+pub fn define_natives(globals: &Environment) {
+    let natives = [
+        NativeFunction::new("len", 1, len),
+        NativeFunction::new("push", 2, push),
+        NativeFunction::new("pop", 1, pop),
+        NativeFunction::new("insert", 3, insert),
+        NativeFunction::new("remove", 2, remove),
+        NativeFunction::new("slice", 3, slice),
+        NativeFunction::new("keys", 1, keys),
+        NativeFunction::new("values", 1, values),
+        NativeFunction::new("has", 2, has),
+        NativeFunction::with_arity("range", Arity::range(2, 3), range),
+        NativeFunction::new("map", 2, map),
+        NativeFunction::new("filter", 2, filter),
+        NativeFunction::new("type", 1, type_of),
+        NativeFunction::new("str", 1, str),
+        NativeFunction::new("num", 1, num),
+        NativeFunction::new("bool", 1, bool),
+        NativeFunction::new("arity", 1, arity),
+        NativeFunction::new("name", 1, name),
+    ];
+    for native in natives {
+        globals.define(native.name, Rc::new(Value::Func(Rc::new(native))));
+    }
+}
+
+pub fn error(name: &str, message: String) -> RloxError {
+    RloxError::RuntimeError {
+        lexeme: name.to_string(),
+        message,
+    }
+}
+
+fn expect_list<'a>(name: &str, value: &'a Value) -> Result<&'a RefCell<Vec<Rc<Value>>>, RloxError> {
+    match value {
+        Value::List(list) => Ok(list),
+        _ => Err(error(
+            name,
+            format!("{} expects a list, not a {}.", name, value.type_name()),
+        )),
+    }
+}
+
+fn expect_map<'a>(name: &str, value: &'a Value) -> Result<&'a RefCell<RloxMap>, RloxError> {
+    match value {
+        Value::Map(map) => Ok(map),
+        _ => Err(error(
+            name,
+            format!("{} expects a map, not a {}.", name, value.type_name()),
+        )),
+    }
+}
+
+fn len(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let len = match args[0].as_ref() {
+        Value::List(list) => list.borrow().len(),
+        Value::Map(map) => map.borrow().len(),
+        Value::Str(s) => s.chars().count(),
+        value => {
+            return Err(error(
+                "len",
+                format!("len expects a list, map or string, not a {}.", value.type_name()),
+            ))
+        }
+    };
+    Ok(Rc::new(Value::Number(len as f64)))
+}
+
+fn push(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    expect_list("push", &args[0])?
+        .borrow_mut()
+        .push(args[1].clone());
+    Ok(Rc::new(Value::Nil))
+}
+
+fn pop(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    expect_list("pop", &args[0])?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| error("pop", "Can't pop from an empty list.".to_string()))
+}
+
+fn insert(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let mut list = expect_list("insert", &args[0])?.borrow_mut();
+    // inserting right after the last element is allowed
+    let index = list_index(&args[1], list.len() + 1, "insert")?;
+    list.insert(index, args[2].clone());
+    Ok(Rc::new(Value::Nil))
+}
+
+fn remove(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    if let Value::Map(map) = args[0].as_ref() {
+        let removed = map.borrow_mut().remove(&args[1], "remove")?;
+        return Ok(removed.unwrap_or_else(|| Rc::new(Value::Nil)));
+    }
+    let mut list = expect_list("remove", &args[0])?.borrow_mut();
+    let index = list_index(&args[1], list.len(), "remove")?;
+    Ok(list.remove(index))
+}
+
+fn slice(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let list = expect_list("slice", &args[0])?.borrow();
+    let start = list_index(&args[1], list.len() + 1, "slice")?;
+    let end = list_index(&args[2], list.len() + 1, "slice")?;
+    if start > end {
+        return Err(error(
+            "slice",
+            format!("Slice start {} is after its end {}.", start, end),
+        ));
+    }
+    interpreter.allocate(Value::new_list(list[start..end].to_vec()))
+}
+
+fn keys(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let map = expect_map("keys", &args[0])?.borrow();
+    let keys = map.entries().map(|(key, _)| key.clone()).collect();
+    interpreter.allocate(Value::new_list(keys))
+}
+
+fn values(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let map = expect_map("values", &args[0])?.borrow();
+    let values = map.entries().map(|(_, value)| value.clone()).collect();
+    interpreter.allocate(Value::new_list(values))
+}
+
+fn has(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let map = expect_map("has", &args[0])?.borrow();
+    let found = map.get(&args[1], "has")?.is_some();
+    Ok(Rc::new(Value::Bool(found)))
+}
+
+pub fn expect_number(name: &str, value: &Value) -> Result<f64, RloxError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        _ => Err(error(
+            name,
+            format!("{} expects a number, not a {}.", name, value.type_name()),
+        )),
+    }
+}
+
+/// Counts from `next` towards `end` by `step`, returning nil once it gets there.
+/// Like any iterator function it is used up after one pass.
+#[derive(Debug)]
+struct RangeIterator {
+    next: Cell<f64>,
+    end: f64,
+    step: f64,
+}
+
+impl RloxCallable for RangeIterator {
+    fn call(&self, _interpreter: &mut Interpreter, _args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+        let current = self.next.get();
+        let done = if self.step > 0.0 {
+            current >= self.end
+        } else {
+            current <= self.end
+        };
+        if done {
+            return Ok(Rc::new(Value::Nil));
+        }
+        self.next.set(current + self.step);
+        Ok(Rc::new(Value::Number(current)))
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::exact(0)
+    }
+}
+
+fn range(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let start = expect_number("range", &args[0])?;
+    let end = expect_number("range", &args[1])?;
+    let step = match args.get(2) {
+        Some(step) => expect_number("range", step)?,
+        None => 1.0,
+    };
+    if step == 0.0 || step.is_nan() {
+        return Err(error("range", "The range step must be a non-zero number.".to_string()));
+    }
+    interpreter.allocate(Value::Func(Rc::new(RangeIterator {
+        next: Cell::new(start),
+        end,
+        step,
+    })))
+}
+
+/// Calls a function value handed to a native, such as the callback given to `map`.
+fn call_function(
+    interpreter: &mut Interpreter,
+    name: &str,
+    function: &Value,
+    args: &[Rc<Value>],
+) -> Result<Rc<Value>, RloxError> {
+    match function {
+        Value::Func(function) if function.arity().accepts(args.len()) => {
+            let line = interpreter.call_line();
+            interpreter.call_value(function, line, args, &[])
+        }
+        Value::Func(function) => Err(error(
+            name,
+            format!(
+                "{} passes {} argument(s) to its function, which takes {}.",
+                name,
+                args.len(),
+                function.arity()
+            ),
+        )),
+        _ => Err(error(
+            name,
+            format!("{} expects a function, not a {}.", name, function.type_name()),
+        )),
+    }
+}
+
+fn map(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let list = expect_list("map", &args[0])?.borrow().clone();
+    let mapped = list
+        .into_iter()
+        .map(|element| call_function(interpreter, "map", &args[1], &[element]))
+        .collect::<Result<Vec<_>, _>>()?;
+    interpreter.allocate(Value::new_list(mapped))
+}
+
+fn filter(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let list = expect_list("filter", &args[0])?.borrow().clone();
+    let mut kept = vec![];
+    for element in list {
+        let keep = call_function(interpreter, "filter", &args[1], std::slice::from_ref(&element))?;
+        if keep.is_truthy() {
+            kept.push(element);
+        }
+    }
+    interpreter.allocate(Value::new_list(kept))
+}
+
+/// `"number"`, `"string"`, `"function"`, `"native"` and so on; see `Value::type_name`.
+fn type_of(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    interpreter.allocate(Value::Str(args[0].type_name().to_string()))
+}
+
+fn str(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    interpreter.allocate(Value::Str(args[0].to_string()))
+}
+
+/// Numbers as they are, strings that spell a number, and true and false as 1 and 0.
+fn num(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let n = match args[0].as_ref() {
+        Value::Number(n) => Some(*n),
+        Value::Str(s) => parse_number(s),
+        Value::Bool(b) => Some(*b as u8 as f64),
+        _ => None,
+    };
+    n.map(|n| Rc::new(Value::Number(n))).ok_or_else(|| {
+        error(
+            "num",
+            format!("Can't convert {} {} to a number.", args[0].type_name(), args[0].repr()),
+        )
+    })
+}
+
+fn bool(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    Ok(Rc::new(Value::Bool(args[0].is_truthy())))
+}
+
+fn expect_callable<'a>(name: &str, value: &'a Value) -> Result<&'a Rc<dyn RloxCallable>, RloxError> {
+    match value {
+        Value::Func(function) => Ok(function),
+        _ => Err(error(
+            name,
+            format!("{} expects a function, not a {}.", name, value.type_name()),
+        )),
+    }
+}
+
+/// The number of arguments the function needs; ones with defaults or a rest parameter take more.
+fn arity(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let function = expect_callable("arity", &args[0])?;
+    Ok(Rc::new(Value::Number(function.arity().min as f64)))
+}
+
+/// The declared name of the function, or nil for a lambda.
+fn name(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let function = expect_callable("name", &args[0])?;
+    Ok(Rc::new(match function.name() {
+        Some(name) => Value::Str(name.to_string()),
+        None => Value::Nil,
+    }))
+}
@@ -1,8 +1,9 @@
 use std::rc::Rc;
-use std::sync::atomic::AtomicUsize;
-use std::sync::atomic::Ordering;
 
+use crate::callable::*;
 use crate::error::*;
+use crate::interpreter::*;
+use crate::pattern::*;
 use crate::scanner::*;
 use crate::stmt::*;
 use crate::expr::*;
@@ -11,18 +12,34 @@ use crate::expr::*;
 pub struct Parser {
     pub tokens: Vec<Token>,
     pub current: usize,
+    next_id: usize,
+    /// The position of the '=>' ending the match guard being parsed, which no arrow
+    /// lambda inside the guard may take for its own.
+    guard_arrow: Option<usize>,
 }
 
+impl Parser {
+    /// Creates a parser numbering its variable expressions from `first_id`, so ids stay
+    /// unique across every source run by the same interpreter.
+    pub fn new(tokens: Vec<Token>, first_id: usize) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            next_id: first_id,
+            guard_arrow: None,
+        }
+    }
 
-// TODO find a better solution
-static NEXT_EXPRESSION_ID: AtomicUsize = AtomicUsize::new(0);
-
-fn next_id() -> usize {
-    NEXT_EXPRESSION_ID.fetch_add(1, Ordering::Relaxed)
-}
+    /// The first id not handed out yet.
+    pub fn next_id(&self) -> usize {
+        self.next_id
+    }
 
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
 
-impl Parser {
     pub fn parse(&mut self) -> Result<Vec<Stmt>, RloxError> {
         let mut statements: Vec<Stmt> = vec![];
         while !self.is_end() {
@@ -30,6 +47,18 @@ impl Parser {
         }
         Ok(statements)
     }
+    /// Parses the whole input as a single expression, as typed at the prompt.
+    pub fn parse_expression(&mut self) -> Result<Expr, RloxError> {
+        let expr = self.expression()?;
+        if !self.is_end() {
+            return Err(RloxError::ParseError {
+                token: self.peek(),
+                current: self.current,
+                message: "Expect end of expression.".to_string(),
+            });
+        }
+        Ok(expr)
+    }
     fn expression(&mut self) -> Result<Expr, RloxError> {
         self.assignment()
     }
@@ -94,7 +123,7 @@ impl Parser {
 
     fn check(&self, token: TokenType) -> bool {
         if self.is_end() {
-            false;
+            return false;
         }
         self.peek().token_type == token
     }
@@ -103,6 +132,11 @@ impl Parser {
         self.tokens[self.current].clone()
     }
 
+    /// The type of the token `offset` places after the current one, if there is one.
+    fn peek_type(&self, offset: usize) -> Option<&TokenType> {
+        self.tokens.get(self.current + offset).map(|t| &t.token_type)
+    }
+
     fn term(&mut self) -> Result<Expr, RloxError> {
         let mut expr = self.factor()?;
         while self.match_token(vec![TokenType::Minus, TokenType::Plus]) {
@@ -119,7 +153,7 @@ impl Parser {
 
     fn factor(&mut self) -> Result<Expr, RloxError> {
         let mut expr = self.unary()?;
-        while self.match_token(vec![TokenType::Slash, TokenType::Star]) {
+        while self.match_token(vec![TokenType::Slash, TokenType::Star, TokenType::Percent]) {
             let operator = self.previous();
             let right = self.unary()?;
             expr = Expr::Binary {
@@ -135,12 +169,63 @@ impl Parser {
         if self.match_token(vec![TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
             let right = self.unary()?;
-            Expr::Unary {
+            return Ok(Expr::Unary {
                 right: Box::new(right),
                 operator,
-            };
+            });
+        }
+        if self.match_token(vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous();
+            let target = self.unary()?;
+            return self.update(target, operator, Expr::Number(1.0), false);
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, RloxError> {
+        let expr = self.call()?;
+        if self.match_token(vec![TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous();
+            return self.update(expr, operator, Expr::Number(1.0), true);
+        }
+        Ok(expr)
+    }
+
+    /// Builds `target op= value`, which `x++` and `++x` are forms of with a value of 1.
+    /// Postfix forms evaluate to the value the target had before.
+    fn update(
+        &mut self,
+        target: Expr,
+        operator: Token,
+        value: Expr,
+        postfix: bool,
+    ) -> Result<Expr, RloxError> {
+        match target {
+            Expr::Variable { id, name, .. } => Ok(Expr::CompoundAssign {
+                id,
+                name,
+                operator,
+                value: Box::new(value),
+                postfix,
+            }),
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => Ok(Expr::CompoundSetIndex {
+                object,
+                bracket,
+                index,
+                operator,
+                value: Box::new(value),
+                postfix,
+            }),
+            _ => Err(RloxError::ParseError {
+                current: self.current,
+                token: operator,
+                message: "Invalid assignment target.".to_string(),
+            }),
         }
-        self.call()
     }
 
     fn primary(&mut self) -> Result<Expr, RloxError> {
@@ -157,7 +242,11 @@ impl Parser {
             let value =if let Some(Literal::Str(val)) = self.previous().literal{
                 val
             }else{
-                return Err(RloxError::InterpreterError);
+                return Err(RloxError::ParseError {
+                    token: self.previous(),
+                    current: self.current,
+                    message: "Expect string literal.".to_string(),
+                });
             };
             // let value = Literal::Str(self.previous().literal);
             return Ok(Expr::String(value));
@@ -166,12 +255,42 @@ impl Parser {
             let value =if let Some(Literal::Number(val)) = self.previous().literal{
                 val
             }else{
-                return Err(RloxError::InterpreterError);
+                return Err(RloxError::ParseError {
+                    token: self.previous(),
+                    current: self.current,
+                    message: "Expect number literal.".to_string(),
+                });
             };
             return Ok(Expr::Number(value));
         }
+        if self.match_token(vec![TokenType::Fun]) {
+            return self.function_expression();
+        }
+        if self.check(TokenType::Identifier)
+            && self.peek_type(1) == Some(&TokenType::Arrow)
+            && self.guard_arrow != Some(self.current + 1)
+        {
+            let parameter = Parameter {
+                name: self.advance().lexeme,
+                default: None,
+                rest: false,
+            };
+            self.advance();
+            return self.arrow_body(vec![parameter]);
+        }
         if self.match_token(vec![TokenType::Identifier]) {
-            return Ok(Expr::Variable { id: next_id(), name: self.previous().lexeme });
+            let name = self.previous();
+            return Ok(Expr::Variable {
+                id: self.new_id(),
+                name: name.lexeme,
+                line: name.line,
+            });
+        }
+        if self.starts_arrow_lambda() {
+            self.advance();
+            let parameters = self.parameters()?;
+            self.advance();
+            return self.arrow_body(parameters);
         }
         if self.match_token(vec![TokenType::LeftParen]) {
             let expr = self.expression()?;
@@ -183,6 +302,23 @@ impl Parser {
                 expression: Box::new(expr),
             });
         }
+        if self.match_token(vec![TokenType::LeftBracket]) {
+            let mut elements: Vec<Expr> = vec![];
+            while !self.check(TokenType::RightBracket) {
+                elements.push(self.expression()?);
+                if !self.match_token(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+            self.consume(
+                TokenType::RightBracket,
+                "Expect ']' after list elements.".to_string(),
+            )?;
+            return Ok(Expr::List { elements });
+        }
+        if self.match_token(vec![TokenType::LeftBrace]) {
+            return self.map_literal();
+        }
         Err(RloxError::ParseError {
             token: self.tokens[self.current].clone(),
             current: self.current,
@@ -210,10 +346,15 @@ impl Parser {
                 TokenType::Class
                 | TokenType::Fun
                 | TokenType::Var
+                | TokenType::Const
+                | TokenType::Import
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
+                | TokenType::Match
+                | TokenType::Throw
+                | TokenType::Try
                 | TokenType::Return => break,
                 _ => {}
             }
@@ -237,7 +378,16 @@ impl Parser {
         if self.match_token(vec![TokenType::Return]) {
             return self.return_statement();
         }
-        if self.match_token(vec![TokenType::LeftBrace]) {
+        if self.match_token(vec![TokenType::Match]) {
+            return self.match_statement();
+        }
+        if self.match_token(vec![TokenType::Throw]) {
+            return self.throw_statement();
+        }
+        if self.match_token(vec![TokenType::Try]) {
+            return self.try_statement();
+        }
+        if !self.starts_map_literal() && self.match_token(vec![TokenType::LeftBrace]) {
             return Ok(Stmt::Block {
                 statements: self.block()?,
             });
@@ -245,10 +395,51 @@ impl Parser {
         self.expression_statement()
     }
 
+    /// A '{' in statement position opens a block unless it is followed by `key:`,
+    /// which no statement can start with.
+    fn starts_map_literal(&self) -> bool {
+        self.check(TokenType::LeftBrace)
+            && matches!(
+                self.peek_type(1),
+                Some(
+                    TokenType::String
+                        | TokenType::Number
+                        | TokenType::Identifier
+                        | TokenType::True
+                        | TokenType::False
+                        | TokenType::Nil
+                )
+            )
+            && self.peek_type(2) == Some(&TokenType::Colon)
+    }
+
+    fn map_literal(&mut self) -> Result<Expr, RloxError> {
+        let brace = self.previous();
+        let mut keys: Vec<Expr> = vec![];
+        let mut values: Vec<Expr> = vec![];
+        while !self.check(TokenType::RightBrace) {
+            keys.push(self.expression()?);
+            self.consume(TokenType::Colon, "Expect ':' after map key.".to_string())?;
+            values.push(self.expression()?);
+            if !self.match_token(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(
+            TokenType::RightBrace,
+            "Expect '}' after map entries.".to_string(),
+        )?;
+        Ok(Expr::Map {
+            brace,
+            keys,
+            values,
+        })
+    }
+
     fn print_statement(&mut self) -> Result<Stmt, RloxError> {
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value.".to_string())?;
-        return Ok(Stmt::Print { expression: value });
+        Ok(Stmt::Print { expression: value })
     }
 
     fn expression_statement(&mut self) -> Result<Stmt, RloxError> {
@@ -257,14 +448,25 @@ impl Parser {
             TokenType::Semicolon,
             "Expect ';' after expression.".to_string(),
         )?;
-        return Ok(Stmt::Expression { expression: value });
+        Ok(Stmt::Expression { expression: value })
     }
 
     fn declaration(&mut self) -> Result<Stmt, RloxError> {
-        let res = if self.match_token(vec![TokenType::Fun]) {
+        // `fun (` starts an anonymous function expression, not a declaration
+        let declares_function =
+            self.check(TokenType::Fun) && self.peek_type(1) != Some(&TokenType::LeftParen);
+        let res = if declares_function {
+            self.advance();
             self.fun_declaration("function")
+        } else if self.match_token(vec![TokenType::Import]) {
+            self.import_declaration()
+        } else if self.starts_from_import() {
+            self.advance();
+            self.import_names_declaration()
         } else if self.match_token(vec![TokenType::Var]) {
             self.var_declaration()
+        } else if self.match_token(vec![TokenType::Const]) {
+            self.const_declaration()
         } else {
             self.statement()
         };
@@ -292,21 +494,134 @@ impl Parser {
         })
     }
 
+    /// `import "path" as name;`, after the 'import'.
+    fn import_declaration(&mut self) -> Result<Stmt, RloxError> {
+        let keyword = self.previous();
+        let path = self.module_path()?;
+        self.consume_contextual("as", "Expect 'as' after module path.")?;
+        let alias = self.consume(
+            TokenType::Identifier,
+            "Expect module name after 'as'.".to_string(),
+        )?;
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after import.".to_string(),
+        )?;
+        Ok(Stmt::Import {
+            keyword,
+            path,
+            alias: Some(alias.lexeme),
+            names: vec![],
+        })
+    }
+
+    /// `from` is only a keyword when a module path follows, so it stays usable as a name.
+    fn starts_from_import(&self) -> bool {
+        self.check(TokenType::Identifier)
+            && self.peek().lexeme == "from"
+            && self.peek_type(1) == Some(&TokenType::String)
+    }
+
+    /// `from "path" import a, b;`, after the 'from'.
+    fn import_names_declaration(&mut self) -> Result<Stmt, RloxError> {
+        let path = self.module_path()?;
+        let keyword = self.consume(
+            TokenType::Import,
+            "Expect 'import' after module path.".to_string(),
+        )?;
+        let mut names = vec![];
+        loop {
+            names.push(
+                self.consume(
+                    TokenType::Identifier,
+                    "Expect name to import.".to_string(),
+                )?
+                .lexeme,
+            );
+            if !self.match_token(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after imported names.".to_string(),
+        )?;
+        Ok(Stmt::Import {
+            keyword,
+            path,
+            alias: None,
+            names,
+        })
+    }
+
+    fn module_path(&mut self) -> Result<String, RloxError> {
+        let token = self.consume(TokenType::String, "Expect module path.".to_string())?;
+        match token.literal {
+            Some(Literal::Str(path)) => Ok(path),
+            _ => Err(RloxError::ParseError {
+                token,
+                current: self.current,
+                message: "Expect module path.".to_string(),
+            }),
+        }
+    }
+
+    /// Consumes an identifier acting as a keyword in one spot only, like the 'as' of an import.
+    fn consume_contextual(&mut self, word: &str, message: &str) -> Result<Token, RloxError> {
+        if self.check(TokenType::Identifier) && self.peek().lexeme == word {
+            return Ok(self.advance());
+        }
+        Err(RloxError::ParseError {
+            token: self.peek(),
+            current: self.current,
+            message: message.to_string(),
+        })
+    }
+
+    fn const_declaration(&mut self) -> Result<Stmt, RloxError> {
+        let name = self.consume(TokenType::Identifier, "Expect constant name.".to_string())?;
+        self.consume(
+            TokenType::Equal,
+            "Expect '=' after constant name, a constant needs a value.".to_string(),
+        )?;
+        let initializer = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.".to_string())?;
+
+        Ok(Stmt::Const {
+            name: name.lexeme,
+            initializer,
+        })
+    }
+
     fn assignment(&mut self) -> Result<Expr, RloxError> {
-        let expr = self.or()?;
+        let expr = self.conditional()?;
 
         if self.match_token(vec![TokenType::Equal]) {
             let equals = self.previous();
             let value = self.assignment()?;
 
 
-            if let Expr::Variable { id, name } = expr {
+            if let Expr::Variable { id, name, line } = expr {
                 return Ok(Expr::Assign {
                     name,
+                    line,
                     value: Box::new(value),
                     id,
                 });
             };
+            if let Expr::Index {
+                object,
+                bracket,
+                index,
+            } = expr
+            {
+                return Ok(Expr::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    value: Box::new(value),
+                });
+            };
 
             return Err(RloxError::ParseError {
                 current: self.current,
@@ -314,6 +629,17 @@ impl Parser {
                 message: "Invalid assignment target.".to_string(),
             });
         }
+        if self.match_token(vec![
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+            TokenType::PercentEqual,
+        ]) {
+            let operator = self.previous();
+            let value = self.assignment()?;
+            return self.update(expr, operator, value, false);
+        }
         Ok(expr)
     }
 
@@ -348,6 +674,39 @@ impl Parser {
         })
     }
 
+    /// `condition ? then : else`, right-associative so `a ? b : c ? d : e` nests to the right.
+    fn conditional(&mut self) -> Result<Expr, RloxError> {
+        let condition = self.coalesce()?;
+        if !self.match_token(vec![TokenType::Question]) {
+            return Ok(condition);
+        }
+        let then_branch = self.expression()?;
+        self.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional expression.".to_string(),
+        )?;
+        let else_branch = self.conditional()?;
+        Ok(Expr::Conditional {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        })
+    }
+
+    fn coalesce(&mut self) -> Result<Expr, RloxError> {
+        let mut expr = self.or()?;
+        while self.match_token(vec![TokenType::QuestionQuestion]) {
+            let operator = self.previous();
+            let right = self.or()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }
+        }
+        Ok(expr)
+    }
+
     fn or(&mut self) -> Result<Expr, RloxError> {
         let mut expr = self.and()?;
         while self.match_token(vec![TokenType::Or]) {
@@ -386,7 +745,11 @@ impl Parser {
     }
 
     fn for_statement(&mut self) -> Result<Stmt, RloxError> {
+        let keyword = self.previous();
         self.consume(TokenType::LeftParen, "Expect '(' after for.".to_string())?;
+        if self.starts_for_in() {
+            return self.for_in_statement(keyword);
+        }
         let initializer = if self.match_token(vec![TokenType::Semicolon]) {
             None
         } else if self.match_token(vec![TokenType::Var]) {
@@ -425,7 +788,7 @@ impl Parser {
             }
         }
         if condition.is_none() {
-            condition = Some(Expr::Boolean(false))
+            condition = Some(Expr::Boolean(true))
         }
         body = Stmt::While {
             condition: condition.expect("cannot be none we just set the value"),
@@ -440,20 +803,227 @@ impl Parser {
         Ok(body)
     }
 
+    /// `for (x in ...)` or `for (var x in ...)`, looking past the '(' already consumed.
+    fn starts_for_in(&self) -> bool {
+        let offset = if self.check(TokenType::Var) { 1 } else { 0 };
+        self.peek_type(offset) == Some(&TokenType::Identifier)
+            && self.peek_type(offset + 1) == Some(&TokenType::In)
+    }
+
+    fn for_in_statement(&mut self, keyword: Token) -> Result<Stmt, RloxError> {
+        self.match_token(vec![TokenType::Var]);
+        let name = self.consume(TokenType::Identifier, "Expect loop variable name.".to_string())?;
+        self.consume(TokenType::In, "Expect 'in' after loop variable.".to_string())?;
+        let iterable = self.expression()?;
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after for clause.".to_string(),
+        )?;
+        let body = Box::new(self.statement()?);
+
+        Ok(Stmt::ForIn {
+            keyword,
+            name: name.lexeme,
+            iterable,
+            body,
+        })
+    }
+
+    /// `match (subject) { pattern if guard => body, ... }`, after the 'match'.
+    fn match_statement(&mut self) -> Result<Stmt, RloxError> {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'.".to_string())?;
+        let subject = self.expression()?;
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after match subject.".to_string(),
+        )?;
+        self.consume(
+            TokenType::LeftBrace,
+            "Expect '{' before match arms.".to_string(),
+        )?;
+
+        let mut arms: Vec<MatchArm> = vec![];
+        while !self.check(TokenType::RightBrace) && !self.is_end() {
+            let pattern = self.pattern()?;
+            let guard = if self.match_token(vec![TokenType::If]) {
+                self.guard_arrow = self.top_level_arrow();
+                let guard = self.expression();
+                self.guard_arrow = None;
+                Some(guard?)
+            } else {
+                None
+            };
+            self.consume(
+                TokenType::Arrow,
+                "Expect '=>' after match pattern.".to_string(),
+            )?;
+            let body = self.statement()?;
+            self.match_token(vec![TokenType::Comma]);
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
+        }
+        self.consume(
+            TokenType::RightBrace,
+            "Expect '}' after match arms.".to_string(),
+        )?;
+
+        Ok(Stmt::Match {
+            keyword,
+            subject,
+            arms,
+        })
+    }
+
+    /// The position of the first '=>' from the current token on that is not inside
+    /// parentheses, brackets or braces.
+    fn top_level_arrow(&self) -> Option<usize> {
+        let mut depth = 0usize;
+        for (i, token) in self.tokens.iter().enumerate().skip(self.current) {
+            match token.token_type {
+                TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                    depth = depth.checked_sub(1)?;
+                }
+                TokenType::Arrow if depth == 0 => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// A whole pattern, checked to bind each name once and the same names in every alternative.
+    fn pattern(&mut self) -> Result<Pattern, RloxError> {
+        let start = self.peek();
+        let mut alternatives = vec![self.single_pattern()?];
+        while self.match_token(vec![TokenType::Pipe]) {
+            alternatives.push(self.single_pattern()?);
+        }
+
+        let mut names = alternatives[0].bindings();
+        names.sort();
+        for alternative in &alternatives[1..] {
+            let mut other = alternative.bindings();
+            other.sort();
+            if other != names {
+                return Err(RloxError::ParseError {
+                    current: self.current,
+                    token: start,
+                    message: "Every alternative of a pattern must bind the same names."
+                        .to_string(),
+                });
+            }
+        }
+        if names.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(RloxError::ParseError {
+                current: self.current,
+                token: start,
+                message: "A pattern can't bind the same name twice.".to_string(),
+            });
+        }
+
+        Ok(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            Pattern::Alternatives(alternatives)
+        })
+    }
+
+    fn single_pattern(&mut self) -> Result<Pattern, RloxError> {
+        let token = self.advance();
+        match token.token_type {
+            TokenType::Number | TokenType::String => match token.literal {
+                Some(Literal::Number(n)) => Ok(Pattern::Literal(Value::Number(n))),
+                Some(Literal::Str(s)) => Ok(Pattern::Literal(Value::Str(s))),
+                _ => Err(RloxError::ParseError {
+                    current: self.current,
+                    token,
+                    message: "Expect literal value.".to_string(),
+                }),
+            },
+            TokenType::Minus if self.check(TokenType::Number) => match self.advance().literal {
+                Some(Literal::Number(n)) => Ok(Pattern::Literal(Value::Number(-n))),
+                _ => Err(RloxError::ParseError {
+                    current: self.current,
+                    token,
+                    message: "Expect number literal.".to_string(),
+                }),
+            },
+            TokenType::True => Ok(Pattern::Literal(Value::Bool(true))),
+            TokenType::False => Ok(Pattern::Literal(Value::Bool(false))),
+            TokenType::Nil => Ok(Pattern::Literal(Value::Nil)),
+            TokenType::Identifier if token.lexeme == "_" => Ok(Pattern::Wildcard),
+            TokenType::Identifier => Ok(Pattern::Binding(token.lexeme)),
+            TokenType::LeftBracket => {
+                let mut elements: Vec<Pattern> = vec![];
+                let mut rest = None;
+                while !self.check(TokenType::RightBracket) {
+                    if self.match_token(vec![TokenType::Ellipsis]) {
+                        let name = self.consume(
+                            TokenType::Identifier,
+                            "Expect name after '...'.".to_string(),
+                        )?;
+                        rest = Some(name.lexeme);
+                        break;
+                    }
+                    elements.push(self.pattern()?);
+                    if !self.match_token(vec![TokenType::Comma]) {
+                        break;
+                    }
+                }
+                self.consume(
+                    TokenType::RightBracket,
+                    "Expect ']' after list pattern, a rest pattern must come last.".to_string(),
+                )?;
+                Ok(Pattern::List { elements, rest })
+            }
+            _ => Err(RloxError::ParseError {
+                current: self.current,
+                token,
+                message: "Expect pattern.".to_string(),
+            }),
+        }
+    }
+
     fn call(&mut self) -> Result<Expr, RloxError> {
         let mut expr = self.primary()?;
 
         loop {
-            match self.match_token(vec![TokenType::LeftParen]) {
-                true => expr = self.finish_call(expr)?,
-                false => break,
+            if self.match_token(vec![TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(vec![TokenType::Dot]) {
+                let name = self.consume(
+                    TokenType::Identifier,
+                    "Expect member name after '.'.".to_string(),
+                )?;
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.match_token(vec![TokenType::LeftBracket]) {
+                let bracket = self.previous();
+                let index = self.expression()?;
+                self.consume(
+                    TokenType::RightBracket,
+                    "Expect ']' after index.".to_string(),
+                )?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
+            } else {
+                break;
             }
         }
         Ok(expr)
     }
 
     fn finish_call(&mut self, expr: Expr) -> Result<Expr, RloxError> {
-        let mut arguments: Vec<Expr> = vec![];
+        let mut arguments: Vec<Argument> = vec![];
 
         if !self.check(TokenType::RightParen) {
             loop {
@@ -464,21 +1034,40 @@ impl Parser {
                         message: "Can't have more than 255 arguments.".to_string(),
                     });
                 }
-                arguments.push(self.expression()?);
+                let name = if self.check(TokenType::Identifier)
+                    && self.peek_type(1) == Some(&TokenType::Colon)
+                {
+                    let name = self.advance().lexeme;
+                    self.advance();
+                    Some(name)
+                } else if arguments.last().is_some_and(|a| a.name.is_some()) {
+                    return Err(RloxError::ParseError {
+                        current: self.current,
+                        token: self.peek(),
+                        message: "Positional arguments can't follow named ones.".to_string(),
+                    });
+                } else {
+                    None
+                };
+                arguments.push(Argument {
+                    name,
+                    value: self.expression()?,
+                });
                 if !self.match_token(vec![TokenType::Comma]) {
                     break;
                 }
             }
         }
 
-        self.consume(
+        let paren = self.consume(
             TokenType::RightParen,
             "Expected ')' after arguments".to_string(),
         )?;
 
         Ok(Expr::Call {
             callee: Box::new(expr),
-            arguments: Box::new(arguments),
+            paren,
+            arguments,
         })
     }
 
@@ -492,8 +1081,26 @@ impl Parser {
             TokenType::LeftParen,
             format!("Expect '(' after {kind} name.").to_string(),
         )?;
+        let parameters = self.parameters()?;
+
+        self.consume(
+            TokenType::LeftBrace,
+            format!("Expect '{{' before {kind} body.").to_string(),
+        )?;
+
+        let body = Rc::new(self.block()?);
+
+        Ok(Stmt::Function {
+            name: name.lexeme,
+            body,
+            parameters: Rc::new(parameters),
+        })
+    }
 
-        let mut parameters: Vec<String> = vec![];
+    /// The parameters after a '(', up to and including the closing ')'. Parameters with
+    /// defaults come after those without, and a rest parameter comes last.
+    fn parameters(&mut self) -> Result<Vec<Parameter>, RloxError> {
+        let mut parameters: Vec<Parameter> = vec![];
 
         if !self.check(TokenType::RightParen) {
             loop {
@@ -504,32 +1111,171 @@ impl Parser {
                         message: "Can't have more than 255 arguments.".to_string(),
                     });
                 }
-                parameters.push(self.consume(
+                let rest = self.match_token(vec![TokenType::Ellipsis]);
+                let name = self.consume(
                     TokenType::Identifier,
-                    format!("Expect parameter name.").to_string(),
-                )?.lexeme);
-                if !self.match_token(vec![TokenType::Comma]) {
+                    "Expect parameter name.".to_string(),
+                )?;
+                let default = if !rest && self.match_token(vec![TokenType::Equal]) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+                if !rest
+                    && default.is_none()
+                    && parameters.last().is_some_and(|p| p.default.is_some())
+                {
+                    return Err(RloxError::ParseError {
+                        current: self.current,
+                        token: name,
+                        message: "A parameter without a default can't follow one with a default."
+                            .to_string(),
+                    });
+                }
+                parameters.push(Parameter {
+                    name: name.lexeme,
+                    default,
+                    rest,
+                });
+                if rest || !self.match_token(vec![TokenType::Comma]) {
                     break;
                 }
             }
         }
 
+        let message = if parameters.last().is_some_and(|p| p.rest) {
+            "Expect ')' after rest parameter, it must come last."
+        } else {
+            "Expect ')' after parameters."
+        };
+        self.consume(TokenType::RightParen, message.to_string())?;
+        Ok(parameters)
+    }
+
+    /// `fun (a, b) { ... }`, after the 'fun'.
+    fn function_expression(&mut self) -> Result<Expr, RloxError> {
         self.consume(
-            TokenType::RightParen,
-            format!("Expect ')' after parameters.").to_string(),
+            TokenType::LeftParen,
+            "Expect '(' after 'fun'.".to_string(),
+        )?;
+        let parameters = self.parameters()?;
+        self.consume(
+            TokenType::LeftBrace,
+            "Expect '{' before function body.".to_string(),
+        )?;
+        let body = self.block()?;
+        Ok(Expr::Lambda {
+            parameters: Rc::new(parameters),
+            body: Rc::new(body),
+        })
+    }
+
+    /// Whether the tokens from the current one on are a parenthesized list followed by
+    /// '=>', as in `(a, b = 1) =>` or `() =>`.
+    fn starts_arrow_lambda(&self) -> bool {
+        if self.peek_type(0) != Some(&TokenType::LeftParen) {
+            return false;
+        }
+        let mut depth = 0;
+        let mut i = 0;
+        while let Some(token_type) = self.peek_type(i) {
+            match token_type {
+                TokenType::LeftParen => depth += 1,
+                TokenType::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.peek_type(i + 1) == Some(&TokenType::Arrow)
+                            && self.guard_arrow != Some(self.current + i + 1);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        false
+    }
+
+    /// The part of `(a, b) => a + b` after the '=>'. A '{' starts a block body,
+    /// anything else is an expression whose value is returned.
+    fn arrow_body(&mut self, parameters: Vec<Parameter>) -> Result<Expr, RloxError> {
+        let body = if self.match_token(vec![TokenType::LeftBrace]) {
+            self.block()?
+        } else {
+            vec![Stmt::Return {
+                value: Some(self.expression()?),
+            }]
+        };
+        Ok(Expr::Lambda {
+            parameters: Rc::new(parameters),
+            body: Rc::new(body),
+        })
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt, RloxError> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after thrown value.".to_string(),
         )?;
+        Ok(Stmt::Throw { keyword, value })
+    }
 
+    /// `try { } catch (e) { } finally { }`, after the 'try'. Either clause may be left
+    /// out, but not both.
+    fn try_statement(&mut self) -> Result<Stmt, RloxError> {
+        let keyword = self.previous();
         self.consume(
             TokenType::LeftBrace,
-            format!("Expect '{{' before {kind} body.").to_string(),
+            "Expect '{' after 'try'.".to_string(),
         )?;
+        let body = self.block()?;
 
-        let body = Rc::new(self.block()?);
+        let (catch_name, catch_body) = if self.match_token(vec![TokenType::Catch]) {
+            self.consume(
+                TokenType::LeftParen,
+                "Expect '(' after 'catch'.".to_string(),
+            )?;
+            let name = self.consume(
+                TokenType::Identifier,
+                "Expect name for the caught value.".to_string(),
+            )?;
+            self.consume(
+                TokenType::RightParen,
+                "Expect ')' after caught value name.".to_string(),
+            )?;
+            self.consume(
+                TokenType::LeftBrace,
+                "Expect '{' before catch body.".to_string(),
+            )?;
+            (Some(name.lexeme), Some(self.block()?))
+        } else {
+            (None, None)
+        };
 
-        Ok(Stmt::Function {
-            name: name.lexeme,
+        let finally_body = if self.match_token(vec![TokenType::Finally]) {
+            self.consume(
+                TokenType::LeftBrace,
+                "Expect '{' after 'finally'.".to_string(),
+            )?;
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch_body.is_none() && finally_body.is_none() {
+            return Err(RloxError::ParseError {
+                current: self.current,
+                token: self.peek(),
+                message: "Expect 'catch' or 'finally' after try block.".to_string(),
+            });
+        }
+        Ok(Stmt::Try {
+            keyword,
             body,
-            parameters: Rc::new(parameters),
+            catch_name,
+            catch_body,
+            finally_body,
         })
     }
 
@@ -547,3 +1293,33 @@ impl Parser {
         Ok(Stmt::Return { value })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The guard of the only arm of the match statement in `source`.
+    fn guard(source: &str) -> Expr {
+        let tokens = Scanner::default().scan_tokens(source.to_string()).unwrap();
+        let statements = Parser::new(tokens, 0).parse().unwrap();
+        match statements.into_iter().next() {
+            Some(Stmt::Match { mut arms, .. }) => arms.remove(0).guard.expect("a guard"),
+            statement => panic!("expected a match statement, not {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn a_guard_ends_at_the_arrow_of_its_arm() {
+        assert!(matches!(guard("match (5) { n if ok => print n; }"), Expr::Variable { .. }));
+        assert!(matches!(guard("match (5) { n if (n > 0) => print n; }"), Expr::Grouping { .. }));
+    }
+
+    #[test]
+    fn a_guard_may_hold_arrow_lambdas_of_its_own() {
+        let source = "match (l) { l if any(l, x => x > 1) => print l; }";
+        let Expr::Call { arguments, .. } = guard(source) else {
+            panic!("expected a call");
+        };
+        assert!(matches!(arguments[1].value, Expr::Lambda { .. }));
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+use crate::error::RloxError;
+use crate::expr::*;
+use crate::interpreter::*;
+use crate::stmt::*;
+
+/// The left side of a `match` arm.
+#[derive(Debug, PartialEq)]
+pub enum Pattern {
+    /// `1`, `"a"`, `true` or `nil`, matching values equal to it.
+    Literal(Value),
+    /// `_`, matching anything without binding it.
+    Wildcard,
+    /// A name, matching anything and binding it for the arm.
+    Binding(String),
+    /// `p | q`, matching when any alternative does. Every alternative binds the same names.
+    Alternatives(Vec<Pattern>),
+    /// `[p, q]`, or `[p, ...rest]` to also take lists with more elements.
+    List {
+        elements: Vec<Pattern>,
+        rest: Option<String>,
+    },
+}
+
+/// `pattern if guard => body`
+#[derive(Debug, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Stmt,
+}
+
+impl Pattern {
+    /// The names the pattern binds, in the order they appear.
+    pub fn bindings(&self) -> Vec<&str> {
+        match self {
+            Pattern::Literal(_) | Pattern::Wildcard => vec![],
+            Pattern::Binding(name) => vec![name],
+            Pattern::Alternatives(alternatives) => alternatives
+                .first()
+                .map_or_else(Vec::new, |first| first.bindings()),
+            Pattern::List { elements, rest } => elements
+                .iter()
+                .flat_map(|element| element.bindings())
+                .chain(rest.as_deref().filter(|&name| name != "_"))
+                .collect(),
+        }
+    }
+
+    /// Tests the value against the pattern, pushing what it binds onto `bound` when it matches.
+    /// The interpreter counts the lists `...rest` makes against its budget.
+    pub fn matches(
+        &self,
+        interpreter: &mut Interpreter,
+        value: &Rc<Value>,
+        bound: &mut Vec<(String, Rc<Value>)>,
+    ) -> Result<bool, RloxError> {
+        match self {
+            Pattern::Literal(literal) => Ok(literal == value.as_ref()),
+            Pattern::Wildcard => Ok(true),
+            Pattern::Binding(name) => {
+                bound.push((name.clone(), value.clone()));
+                Ok(true)
+            }
+            Pattern::Alternatives(alternatives) => {
+                for alternative in alternatives {
+                    let mark = bound.len();
+                    if alternative.matches(interpreter, value, bound)? {
+                        return Ok(true);
+                    }
+                    bound.truncate(mark);
+                }
+                Ok(false)
+            }
+            Pattern::List { elements, rest } => {
+                let Value::List(list) = value.as_ref() else {
+                    return Ok(false);
+                };
+                // a copy, so nested patterns are free to look at the list again
+                let list = list.borrow().clone();
+                let fits = match rest {
+                    Some(_) => list.len() >= elements.len(),
+                    None => list.len() == elements.len(),
+                };
+                if !fits {
+                    return Ok(false);
+                }
+                for (element, value) in elements.iter().zip(&list) {
+                    if !element.matches(interpreter, value, bound)? {
+                        return Ok(false);
+                    }
+                }
+                if let Some(rest) = rest.as_ref().filter(|&name| name != "_") {
+                    let remaining = list[elements.len()..].to_vec();
+                    bound.push((rest.clone(), interpreter.allocate(Value::new_list(remaining))?));
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Literal(value) => write!(f, "{}", value.repr()),
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding(name) => write!(f, "{}", name),
+            Pattern::Alternatives(alternatives) => {
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", alternative)?;
+                }
+                Ok(())
+            }
+            Pattern::List { elements, rest } => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                if let Some(rest) = rest {
+                    if !elements.is_empty() {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "...{}", rest)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use crate::environment::*;
+use crate::error::*;
+use crate::parser::*;
+use crate::scanner::*;
+
+pub const PROMPT: &str = "> ";
+pub const CONTINUATION_PROMPT: &str = "... ";
+
+pub const META_HELP: &str = "\
+:env            List the global variables and their values
+:type <expr>    Print the type of an expression
+:ast <expr>     Print the syntax tree of an expression
+:load <file>    Run a script in the current session
+:reset          Discard all definitions and start a fresh interpreter
+:time <code>    Run code and report how long it took
+:help           Print this message
+:quit           Leave the prompt";
+
+/// A prompt command starting with ':' rather than Lox source.
+pub enum MetaCommand {
+    Env,
+    Type(String),
+    Ast(String),
+    Load(String),
+    Reset,
+    Time(String),
+    Help,
+    Quit,
+}
+
+impl MetaCommand {
+    pub fn parse(line: &str) -> Result<MetaCommand, String> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        let required = |command: fn(String) -> MetaCommand| {
+            if argument.is_empty() {
+                Err(format!("{} expects an argument", name))
+            } else {
+                Ok(command(argument.to_string()))
+            }
+        };
+        let bare = |command: MetaCommand| {
+            if argument.is_empty() {
+                Ok(command)
+            } else {
+                Err(format!("{} takes no arguments", name))
+            }
+        };
+        match name {
+            ":env" => bare(MetaCommand::Env),
+            ":type" => required(MetaCommand::Type),
+            ":ast" => required(MetaCommand::Ast),
+            ":load" => required(MetaCommand::Load),
+            ":reset" => bare(MetaCommand::Reset),
+            ":time" => required(MetaCommand::Time),
+            ":help" => bare(MetaCommand::Help),
+            ":quit" | ":q" => bare(MetaCommand::Quit),
+            _ => Err(format!("unknown command {}, try :help", name)),
+        }
+    }
+}
+
+/// Editor helper completing keywords and the names currently defined in the globals.
+pub struct RloxHelper {
+    keywords: Vec<String>,
+    globals: Rc<RefCell<Environment>>,
+}
+
+impl RloxHelper {
+    pub fn new(globals: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            keywords: Scanner::default().keywords().cloned().collect(),
+            globals,
+        }
+    }
+}
+
+impl Completer for RloxHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((pos, vec![]));
+        }
+
+        let mut candidates: Vec<String> = self
+            .keywords
+            .iter()
+            .cloned()
+            .chain(self.globals.borrow().names())
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for RloxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for RloxHelper {}
+
+impl Validator for RloxHelper {}
+
+impl Helper for RloxHelper {}
+
+/// Returns true when the input is a bare expression, as in `1 + 2` without a trailing ';'.
+pub fn is_expression(source: &str) -> bool {
+    match Scanner::default().scan_tokens(source.to_string()) {
+        Ok(tokens) => Parser::new(tokens, 0).parse_expression().is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Returns true when the source ends before a string, block, grouping, list or map is
+/// closed, so the prompt should keep reading lines. Any other mistake, such as a missing
+/// ';', is reported straight away.
+pub fn is_incomplete(source: &str) -> bool {
+    let tokens = match Scanner::default().scan_tokens(source.to_string()) {
+        Ok(tokens) => tokens,
+        Err(RloxError::UnterminatedStringError { .. }) => return true,
+        Err(_) => return false,
+    };
+    let mut open = 0usize;
+    for token in tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => open += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                // more closed than opened can't be fixed by reading on
+                match open.checked_sub(1) {
+                    Some(depth) => open = depth,
+                    None => return false,
+                }
+            }
+            _ => {}
+        }
+    }
+    open > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use rustyline::history::DefaultHistory;
+
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    fn complete(line: &str) -> (usize, Vec<String>) {
+        let helper = RloxHelper::new(Interpreter::new().globals());
+        let history = DefaultHistory::new();
+        helper.complete(line, line.len(), &Context::new(&history)).unwrap()
+    }
+
+    #[test]
+    fn completion_finds_the_word_before_the_cursor() {
+        assert_eq!(complete("print le"), (6, vec!["len".to_string()]));
+        // after a character of more than one byte
+        assert_eq!(complete("print \"é"), ("print \"é".len(), vec![]));
+        assert_eq!(complete("\"é\" + le"), ("\"é\" + ".len(), vec!["len".to_string()]));
+    }
+
+    #[test]
+    fn only_unclosed_delimiters_and_strings_continue_the_input() {
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("var l = [1,"));
+        assert!(is_incomplete("print \"unclosed"));
+        assert!(!is_incomplete("print 1"));
+        assert!(!is_incomplete("var a ="));
+        assert!(!is_incomplete("fun f() { }"));
+        assert!(!is_incomplete("print 1); {"));
+    }
+}
@@ -2,24 +2,30 @@ use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Rc;
 
-use crate::{error::*, expr::*, interpreter::*, stmt::*};
+use crate::{callable::*, error::*, expr::*, stmt::*};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 enum FunctionType {
     None,
     Function,
 }
+
+/// What a local scope knows about one of its names.
+#[derive(Copy, Clone)]
+struct Binding {
+    defined: bool,
+    mutable: bool,
+}
+
 pub struct Resolver {
-    pub interpreter: Interpreter,
-    scopes: Vec<HashMap<String, bool>>,
+    scopes: Vec<HashMap<String, Binding>>,
     current_function: FunctionType,
     depth_map: HashMap<usize, usize>,
 }
 
 impl Resolver {
-    pub fn new(interpreter: Interpreter) -> Self {
+    pub fn new() -> Self {
         Self {
-            interpreter,
             scopes: Vec::new(),
             depth_map: HashMap::new(),
             current_function: FunctionType::None,
@@ -29,11 +35,23 @@ impl Resolver {
     fn begin_scope(&mut self) {
         self.scopes.push(HashMap::new())
     }
-    pub fn resolve(&mut self, statements: &Vec<Stmt>) -> Result<(), RloxError> {
-        self.resolve_statements(statements)?;
-        let scopes = std::mem::take(&mut self.depth_map);
-        self.interpreter.add_scopes(scopes);
-        Ok(())
+    /// Resolves top-level statements and returns the scope depth of every local they use.
+    /// On error the resolver is put back at the top level so it can be reused.
+    pub fn resolve(&mut self, statements: &Vec<Stmt>) -> Result<HashMap<usize, usize>, RloxError> {
+        let result = self.resolve_statements(statements);
+        self.finish(result)
+    }
+    pub fn resolve_expr(&mut self, expr: &Expr) -> Result<HashMap<usize, usize>, RloxError> {
+        let result = self.resolve_expression(expr);
+        self.finish(result)
+    }
+    fn finish(&mut self, result: Result<(), RloxError>) -> Result<HashMap<usize, usize>, RloxError> {
+        let depths = std::mem::take(&mut self.depth_map);
+        if result.is_err() {
+            self.scopes.clear();
+            self.current_function = FunctionType::None;
+        }
+        result.map(|_| depths)
     }
     pub fn resolve_statements(&mut self, statements: &Vec<Stmt>) -> Result<(), RloxError> {
         for statement in statements.deref() {
@@ -62,6 +80,11 @@ impl Resolver {
                 };
                 self.define(name);
             }
+            Stmt::Const { name, initializer } => {
+                self.declare_const(name);
+                self.resolve_expression(initializer)?;
+                self.define(name);
+            }
             Stmt::If {
                 condition,
                 then_branch,
@@ -77,6 +100,75 @@ impl Resolver {
                 self.resolve_expression(condition)?;
                 self.resolve_statement(body.as_ref())?;
             }
+            Stmt::Match {
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                self.resolve_expression(subject)?;
+                for arm in arms {
+                    self.begin_scope();
+                    for name in arm.pattern.bindings() {
+                        self.declare(name);
+                        self.define(name);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expression(guard)?;
+                    }
+                    self.resolve_statement(&arm.body)?;
+                    self.end_scope();
+                }
+            }
+            Stmt::Import {
+                keyword: _,
+                path: _,
+                alias,
+                names,
+            } => {
+                for name in alias.iter().chain(names) {
+                    self.declare(name);
+                    self.define(name);
+                }
+            }
+            Stmt::Throw { keyword: _, value } => {
+                self.resolve_expression(value)?;
+            }
+            Stmt::Try {
+                keyword: _,
+                body,
+                catch_name,
+                catch_body,
+                finally_body,
+            } => {
+                self.begin_scope();
+                self.resolve_statements(body)?;
+                self.end_scope();
+                if let (Some(name), Some(handler)) = (catch_name, catch_body) {
+                    self.begin_scope();
+                    self.declare(name);
+                    self.define(name);
+                    self.resolve_statements(handler)?;
+                    self.end_scope();
+                }
+                if let Some(finally_body) = finally_body {
+                    self.begin_scope();
+                    self.resolve_statements(finally_body)?;
+                    self.end_scope();
+                }
+            }
+            Stmt::ForIn {
+                keyword: _,
+                name,
+                iterable,
+                body,
+            } => {
+                self.resolve_expression(iterable)?;
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+                self.resolve_statement(body.as_ref())?;
+                self.end_scope();
+            }
             Stmt::Function {
                 name,
                 parameters,
@@ -84,11 +176,14 @@ impl Resolver {
             } => {
                 self.declare(name);
                 self.define(name);
-                self.resolve_function(name, parameters, body, FunctionType::Function)?;
+                self.resolve_function(parameters, body, FunctionType::Function)?;
             }
             Stmt::Return { value } => {
                 if self.current_function == FunctionType::None {
-                    return Err(RloxError::InterpreterError);
+                    return Err(RloxError::ResolveError {
+                        name: "return".to_string(),
+                        message: "Can't return from top-level code.".to_string(),
+                    });
                 }
                 if let Some(val) = value {
                     self.resolve_expression(val)?;
@@ -109,15 +204,71 @@ impl Resolver {
                 self.resolve_expression(left)?;
                 self.resolve_expression(right)?;
             }
-            Expr::Call { callee, arguments } => {
+            Expr::Call {
+                callee,
+                paren: _,
+                arguments,
+            } => {
                 self.resolve_expression(callee)?;
-                for arg in arguments.as_ref() {
-                    self.resolve_expression(arg)?;
+                for arg in arguments {
+                    self.resolve_expression(&arg.value)?;
                 }
             }
             Expr::Grouping { expression } => {
                 self.resolve_expression(expression)?;
             }
+            Expr::Get { object, name: _ } => {
+                self.resolve_expression(object)?;
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expression(condition)?;
+                self.resolve_expression(then_branch)?;
+                self.resolve_expression(else_branch)?;
+            }
+            Expr::List { elements } => {
+                for element in elements {
+                    self.resolve_expression(element)?;
+                }
+            }
+            Expr::Map {
+                brace: _,
+                keys,
+                values,
+            } => {
+                for (key, value) in keys.iter().zip(values) {
+                    self.resolve_expression(key)?;
+                    self.resolve_expression(value)?;
+                }
+            }
+            Expr::Index {
+                object,
+                bracket: _,
+                index,
+            } => {
+                self.resolve_expression(object)?;
+                self.resolve_expression(index)?;
+            }
+            Expr::SetIndex {
+                object,
+                bracket: _,
+                index,
+                value,
+            }
+            | Expr::CompoundSetIndex {
+                object,
+                bracket: _,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expression(object)?;
+                self.resolve_expression(index)?;
+                self.resolve_expression(value)?;
+            }
             Expr::Unary { operator: _, right } => {
                 self.resolve_expression(right)?;
             }
@@ -129,15 +280,34 @@ impl Resolver {
                 self.resolve_expression(left)?;
                 self.resolve_expression(right)?;
             }
-            Expr::Variable { id, name } => {
+            Expr::Variable { id, name, .. } => {
                 if let Some(local) = self.scopes.last() {
-                    if local.get::<str>(name) == Some(&false) {
-                        return Err(RloxError::InterpreterError);
+                    if local.get(name).is_some_and(|binding| !binding.defined) {
+                        return Err(RloxError::ResolveError {
+                            name: name.to_string(),
+                            message: "Can't read local variable in its own initializer.".to_string(),
+                        });
                     }
                     self.resolve_local(*id, name);
                 }
             }
-            Expr::Assign { id, name, value } => {
+            Expr::Lambda { parameters, body } => {
+                self.resolve_function(parameters, body, FunctionType::Function)?;
+            }
+            Expr::Assign {
+                id,
+                name,
+                line: _,
+                value,
+            }
+            | Expr::CompoundAssign {
+                id,
+                name,
+                operator: _,
+                value,
+                postfix: _,
+            } => {
+                self.check_assignable(name)?;
                 self.resolve_expression(value)?;
                 self.resolve_local(*id, name);
             }
@@ -150,49 +320,72 @@ impl Resolver {
     }
 
     fn declare(&mut self, name: &str) {
-        match self.scopes.last_mut() {
-            Some(scope) => {
-                scope.insert(name.to_string(), false);
-            }
-            None => {}
+        if let Some(scope) = self.scopes.last_mut() {
+            let binding = Binding {
+                defined: false,
+                mutable: true,
+            };
+            scope.insert(name.to_string(), binding);
+        }
+    }
+
+    fn declare_const(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let binding = Binding {
+                defined: false,
+                mutable: false,
+            };
+            scope.insert(name.to_string(), binding);
         }
     }
 
     fn define(&mut self, name: &str) {
-        match self.scopes.last_mut() {
-            Some(scope) => {
-                scope.insert(name.to_string(), true);
-            }
-            None => {}
+        if let Some(binding) = self.scopes.last_mut().and_then(|scope| scope.get_mut(name)) {
+            binding.defined = true;
         }
     }
 
-    fn resolve_local(&mut self, depth: usize, name: &str) {
+    /// Rejects assignments to local constants. Globals aren't tracked here, the
+    /// interpreter checks those when the assignment runs.
+    fn check_assignable(&self, name: &str) -> Result<(), RloxError> {
+        let binding = self.scopes.iter().rev().find_map(|scope| scope.get(name));
+        match binding {
+            Some(binding) if !binding.mutable => Err(RloxError::ResolveError {
+                name: name.to_string(),
+                message: format!("Can't assign to constant '{}'.", name),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve_local(&mut self, id: usize, name: &str) {
         for (index, scope) in self.scopes.iter().rev().enumerate() {
             if scope.contains_key(name) {
-                self.depth_map.insert(depth, index);
+                self.depth_map.insert(id, index);
+                break;
             }
         }
     }
 
     fn resolve_function(
         &mut self,
-        name: &str,
-        parameters: &Vec<String>,
+        parameters: &[Parameter],
         body: &Rc<Vec<Stmt>>,
         function_type: FunctionType,
     ) -> Result<(), RloxError> {
-        self.declare(name);
-        self.define(name);
         let enclosing_function = self.current_function;
         self.current_function = function_type;
         self.begin_scope();
-        for token in parameters {
-            self.declare(&token);
-            self.define(&token);
+        // a default sees the parameters before it, evaluated in the same scope as the body
+        for parameter in parameters {
+            if let Some(default) = &parameter.default {
+                self.resolve_expression(default)?;
+            }
+            self.declare(&parameter.name);
+            self.define(&parameter.name);
         }
 
-        self.resolve_statements(&body)?;
+        self.resolve_statements(body)?;
         self.end_scope();
         self.current_function = enclosing_function;
         Ok(())
@@ -22,19 +22,27 @@ impl Default for Scanner {
             line: 1,
             keywords: vec![
                 ("and", TokenType::And),
+                ("catch", TokenType::Catch),
                 ("class", TokenType::Class),
+                ("const", TokenType::Const),
                 ("else", TokenType::Else),
                 ("false", TokenType::False),
+                ("finally", TokenType::Finally),
                 ("for", TokenType::For),
                 ("fun", TokenType::Fun),
                 ("if", TokenType::If),
+                ("import", TokenType::Import),
+                ("in", TokenType::In),
+                ("match", TokenType::Match),
                 ("nil", TokenType::Nil),
                 ("or", TokenType::Or),
                 ("print", TokenType::Print),
                 ("return", TokenType::Return),
                 ("super", TokenType::Super),
                 ("this", TokenType::This),
+                ("throw", TokenType::Throw),
                 ("true", TokenType::True),
+                ("try", TokenType::Try),
                 ("var", TokenType::Var),
                 ("while", TokenType::While),
             ]
@@ -61,6 +69,9 @@ impl Scanner {
         });
         Ok(self.tokens.to_vec())
     }
+    pub fn keywords(&self) -> impl Iterator<Item = &String> {
+        self.keywords.keys()
+    }
     fn is_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -72,12 +83,60 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen, None),
             '{' => self.add_token(TokenType::LeftBrace, None),
             '}' => self.add_token(TokenType::RightBrace, None),
+            '[' => self.add_token(TokenType::LeftBracket, None),
+            ']' => self.add_token(TokenType::RightBracket, None),
             ',' => self.add_token(TokenType::Comma, None),
-            '.' => self.add_token(TokenType::Dot, None),
-            '-' => self.add_token(TokenType::Minus, None),
-            '+' => self.add_token(TokenType::Plus, None),
+            ':' => self.add_token(TokenType::Colon, None),
+            '|' => self.add_token(TokenType::Pipe, None),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    self.add_token(TokenType::Ellipsis, None)
+                } else {
+                    self.add_token(TokenType::Dot, None)
+                }
+            }
+            '-' => {
+                if self.match_next_token('-') {
+                    self.add_token(TokenType::MinusMinus, None)
+                } else if self.match_next_token('=') {
+                    self.add_token(TokenType::MinusEqual, None)
+                } else {
+                    self.add_token(TokenType::Minus, None)
+                }
+            }
+            '+' => {
+                if self.match_next_token('+') {
+                    self.add_token(TokenType::PlusPlus, None)
+                } else if self.match_next_token('=') {
+                    self.add_token(TokenType::PlusEqual, None)
+                } else {
+                    self.add_token(TokenType::Plus, None)
+                }
+            }
             ';' => self.add_token(TokenType::Semicolon, None),
-            '*' => self.add_token(TokenType::Star, None),
+            '*' => {
+                if self.match_next_token('=') {
+                    self.add_token(TokenType::StarEqual, None)
+                } else {
+                    self.add_token(TokenType::Star, None)
+                }
+            }
+            '%' => {
+                if self.match_next_token('=') {
+                    self.add_token(TokenType::PercentEqual, None)
+                } else {
+                    self.add_token(TokenType::Percent, None)
+                }
+            }
+            '?' => {
+                if self.match_next_token('?') {
+                    self.add_token(TokenType::QuestionQuestion, None)
+                } else {
+                    self.add_token(TokenType::Question, None)
+                }
+            }
             '!' => {
                 if self.match_next_token('=') {
                     self.add_token(TokenType::BangEqual, None)
@@ -88,6 +147,8 @@ impl Scanner {
             '=' => {
                 if self.match_next_token('=') {
                     self.add_token(TokenType::EqualEqual, None)
+                } else if self.match_next_token('>') {
+                    self.add_token(TokenType::Arrow, None)
                 } else {
                     self.add_token(TokenType::Equal, None)
                 }
@@ -108,15 +169,12 @@ impl Scanner {
             }
             '/' => {
                 if self.match_next_token('/') {
-                    loop {
-                        let ch = self.peek();
-                        if ch != '\n' || ch != '\0' {
-                            self.advance();
-                        } else {
-                            break;
-                        }
+                    while self.peek() != '\n' && !self.is_end() {
+                        self.advance();
                     }
                     Ok(())
+                } else if self.match_next_token('=') {
+                    self.add_token(TokenType::SlashEqual, None)
                 } else {
                     self.add_token(TokenType::Slash, None)
                 }
@@ -129,7 +187,7 @@ impl Scanner {
                 Ok(())
             }
             _ => {
-                if token.is_alphabetic() {
+                if token.is_alphabetic() || token == '_' {
                     self.identifier()
                 } else {
                     Err(RloxError::ScanError {
@@ -153,7 +211,7 @@ impl Scanner {
             literal,
             line: self.line,
         });
-        return Ok(());
+        Ok(())
     }
     fn match_next_token(&mut self, match_token: char) -> bool {
         match self.source.get(self.current) {
@@ -188,16 +246,8 @@ impl Scanner {
 
         self.advance();
         let value = self.source[self.start + 1..self.current - 1].to_owned();
-        let string_literal: &str = &String::from_utf8(value)
-            .expect("should be a valid string passed.")
-            .to_lowercase();
-        let (token_type, literal) = match string_literal {
-            "true" => (TokenType::True, Some(Literal::True)),
-            "false" => (TokenType::False, Some(Literal::False)),
-            "null" => (TokenType::Nil, Some(Literal::Nil)),
-            v => (TokenType::String, Some(Literal::Str(v.to_owned()))),
-        };
-        self.add_token(token_type, literal)
+        let string_literal = String::from_utf8(value).expect("should be a valid string passed.");
+        self.add_token(TokenType::String, Some(Literal::Str(string_literal)))
     }
 
     fn number(&mut self) -> Result<(), RloxError> {
@@ -223,7 +273,7 @@ impl Scanner {
     }
 
     fn identifier(&mut self) -> Result<(), RloxError> {
-        while self.peek().is_ascii_alphanumeric() {
+        while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
             self.advance();
         }
         let value = self.source[self.start..self.current].to_owned();
@@ -249,23 +299,39 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
+    Pipe,
     Dot,
     Minus,
     Plus,
     Semicolon,
     Slash,
     Star,
+    Percent,
 
     // One or two character tokens.
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    Question,
+    QuestionQuestion,
+    Ellipsis,
+    PlusPlus,
+    MinusMinus,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
 
     // Literals.
     Identifier,
@@ -274,19 +340,27 @@ pub enum TokenType {
 
     // Keywords.
     And,
+    Catch,
     Class,
+    Const,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
+    In,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
@@ -298,9 +372,6 @@ pub enum Literal {
     Identifier(String),
     Str(String),
     Number(f64),
-    True,
-    False,
-    Nil,
 }
 
 impl Hash for Literal {
@@ -309,9 +380,6 @@ impl Hash for Literal {
             Literal::Identifier(i) => i.hash(state),
             Literal::Str(s) => s.hash(state),
             Literal::Number(n) => n.to_bits().hash(state),
-            Literal::True => true.hash(state),
-            Literal::False => false.hash(state),
-            Literal::Nil => "".hash(state),
         }
     }
 }
@@ -323,9 +391,6 @@ impl PartialEq for Literal {
             (Literal::Identifier(fst), Literal::Identifier(snd)) => fst.eq(snd),
             (Literal::Str(fst), Literal::Str(snd)) => fst.eq(snd),
             (Literal::Number(fst), Literal::Number(snd)) => fst.eq(snd),
-            (Literal::True, Literal::True) => true,
-            (Literal::False, Literal::False) => true,
-            (Literal::Nil, Literal::Nil) => true,
             (_, _) => false,
         }
     }
@@ -0,0 +1,304 @@
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::budget::Budget;
+use crate::error::*;
+use crate::expr::Expr;
+use crate::interpreter::*;
+use crate::parser::*;
+use crate::resolver::*;
+use crate::scanner::*;
+use crate::stmt::Stmt;
+
+/// Runs successive sources against one interpreter, the way the prompt feeds it lines.
+///
+/// Every source sees the globals defined by the ones before it and may redefine them.
+/// Expression ids keep counting across sources, so the resolved locals of functions
+/// defined earlier stay valid. A failing source leaves nothing half done: the resolver
+/// is back at the top level and the interpreter back in the global environment.
+pub struct Session {
+    interpreter: Interpreter,
+    resolver: Resolver,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::with_interpreter(Interpreter::new())
+    }
+
+    /// A session whose sources may use the I/O natives.
+    pub fn with_io() -> Self {
+        Self::with_interpreter(Interpreter::with_io())
+    }
+
+    fn with_interpreter(interpreter: Interpreter) -> Self {
+        Self {
+            interpreter,
+            resolver: Resolver::new(),
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    /// Makes the command line arguments following the script visible as the global list `argv`.
+    pub fn set_script_args(&mut self, args: &[String]) {
+        self.interpreter.set_script_args(args);
+        let args = args
+            .iter()
+            .map(|arg| Rc::new(Value::Str(arg.clone())))
+            .collect();
+        self.interpreter
+            .globals()
+            .borrow()
+            .define("argv", Rc::new(Value::new_list(args)));
+    }
+
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.interpreter.set_max_call_depth(depth);
+    }
+
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.interpreter.set_budget(budget);
+    }
+
+    /// Makes imports in the sources run from now on relative to the script at `path`.
+    pub fn set_script_path(&mut self, path: &Path) {
+        self.interpreter.set_script_path(path);
+    }
+
+    /// Drops every definition and starts over with a fresh interpreter, which keeps
+    /// whether I/O is allowed, the call depth limit and the cancel handle.
+    pub fn reset(&mut self) {
+        let max_call_depth = self.interpreter.max_call_depth();
+        let cancel = self.interpreter.cancel_handle();
+        *self = match self.interpreter.has_io() {
+            true => Session::with_io(),
+            false => Session::new(),
+        };
+        self.set_max_call_depth(max_call_depth);
+        self.interpreter.set_cancel_handle(cancel);
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), RloxError> {
+        let statements = self.compile(source)?;
+        self.interpreter.interpret(statements)
+    }
+
+    /// Scans, parses and resolves the source without running it.
+    pub fn check(&mut self, source: &str) -> Result<(), RloxError> {
+        let statements = self.parse(source)?;
+        self.resolver.resolve(&statements).map(|_| ())
+    }
+
+    /// Evaluates a source holding a single bare expression.
+    pub fn evaluate(&mut self, source: &str) -> Result<Rc<Value>, RloxError> {
+        let expr = self.parse_expression(source)?;
+        self.evaluate_expr(&expr)
+    }
+
+    /// Parses a source holding a single bare expression without resolving or running it.
+    pub fn parse_expression(&mut self, source: &str) -> Result<Expr, RloxError> {
+        let tokens = Scanner::default().scan_tokens(source.to_string())?;
+        let mut parser = Parser::new(tokens, self.interpreter.next_expression_id());
+        let expr = parser.parse_expression();
+        self.interpreter.set_next_expression_id(parser.next_id());
+        expr
+    }
+
+    fn evaluate_expr(&mut self, expr: &Expr) -> Result<Rc<Value>, RloxError> {
+        let depths = self.resolver.resolve_expr(expr)?;
+        self.interpreter.add_scopes(depths);
+        self.interpreter.interpret_expression(expr)
+    }
+
+    fn compile(&mut self, source: &str) -> Result<Vec<Stmt>, RloxError> {
+        let statements = self.parse(source)?;
+        let depths = self.resolver.resolve(&statements)?;
+        self.interpreter.add_scopes(depths);
+        Ok(statements)
+    }
+
+    fn parse(&mut self, source: &str) -> Result<Vec<Stmt>, RloxError> {
+        let tokens = Scanner::default().scan_tokens(source.to_string())?;
+        let mut parser = Parser::new(tokens, self.interpreter.next_expression_id());
+        let statements = parser.parse();
+        self.interpreter.set_next_expression_id(parser.next_id());
+        statements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::budget::Limit;
+
+    /// Runs the sources in order in one session, returning how the last one went.
+    fn run_all(session: &mut Session, sources: &[&str]) -> Result<(), RloxError> {
+        let (last, earlier) = sources.split_last().expect("at least one source");
+        for source in earlier {
+            session.run(source).expect("earlier sources run");
+        }
+        session.run(last)
+    }
+
+    #[test]
+    fn a_later_source_can_redefine_a_global() {
+        let mut session = Session::new();
+        run_all(&mut session, &["var a = 1;", "var a = \"two\";"]).unwrap();
+        assert_eq!(*session.evaluate("a").unwrap(), Value::Str("two".to_string()));
+    }
+
+    #[test]
+    fn a_source_failing_inside_a_block_leaves_the_next_one_in_the_globals() {
+        let mut session = Session::new();
+        let result = run_all(&mut session, &["var a = 1;", "{ var a = 2; nil + 1; }"]);
+        assert!(result.is_err());
+        run_all(&mut session, &["a = a + 1;"]).unwrap();
+        assert_eq!(*session.evaluate("a").unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn a_closure_from_an_earlier_source_still_finds_its_locals() {
+        let mut session = Session::new();
+        run_all(
+            &mut session,
+            &[
+                "fun counter() { var n = 0; fun count() { n = n + 1; return n; } return count; }",
+                "var count = counter();",
+                "count();",
+            ],
+        )
+        .unwrap();
+        assert_eq!(*session.evaluate("count()").unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn the_resolver_is_usable_again_after_a_resolve_error() {
+        let mut session = Session::new();
+        let result = run_all(&mut session, &["fun f() { var a = 1; { var b = b; } }"]);
+        assert!(matches!(result, Err(RloxError::ResolveError { .. })), "{:?}", result);
+        run_all(&mut session, &["var b = 1; fun g() { return b; }"]).unwrap();
+        assert_eq!(*session.evaluate("g()").unwrap(), Value::Number(1.0));
+    }
+
+    /// Runs `test` on a thread with the 2 MiB of stack Rust gives spawned threads by default.
+    fn on_default_thread<T: Send + 'static>(test: impl FnOnce() -> T + Send + 'static) -> T {
+        thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn runaway_recursion_fails_instead_of_overflowing_a_default_thread_stack() {
+        let error = on_default_thread(|| {
+            let mut session = Session::new();
+            let result = run_all(&mut session, &["fun f(n) { return f(n + 1); } f(0);"]);
+            format!("{:?}", result.expect_err("the recursion never ends"))
+        });
+        let expected = format!("Stack overflow after {} nested calls.", DEFAULT_MAX_CALL_DEPTH);
+        assert!(error.contains(&expected), "{}", error);
+    }
+
+    #[test]
+    fn recursion_hundreds_of_calls_deep_works_on_a_default_thread_stack() {
+        let depth = on_default_thread(|| {
+            let mut session = Session::new();
+            run_all(&mut session, &["fun d(n) { if (n == 0) return 0; return 1 + d(n - 1); }"])
+                .unwrap();
+            session.evaluate("d(900)").unwrap().to_string()
+        });
+        assert_eq!(depth, "900");
+    }
+
+    #[test]
+    fn lists_nested_very_deep_print_compare_and_drop_without_overflowing() {
+        let (printed, compared) = on_default_thread(|| {
+            let mut session = Session::new();
+            run_all(
+                &mut session,
+                &["var a = []; var b = []; for (i in range(0, 200000)) { a = [a]; b = [b]; }"],
+            )
+            .unwrap();
+            let printed = session.evaluate("str(a)").unwrap().to_string();
+            let compared = format!("{:?}", session.evaluate("a == b").unwrap_err());
+            run_all(&mut session, &["a = nil;"]).unwrap();
+            (printed, compared)
+        });
+        let expected = "[".repeat(MAX_VALUE_DEPTH) + "[...]" + &"]".repeat(MAX_VALUE_DEPTH);
+        assert_eq!(printed, expected);
+        assert!(compared.contains("Can't compare lists or maps nested more than"), "{}", compared);
+    }
+
+    #[test]
+    fn functions_are_equal_only_to_themselves() {
+        let mut session = Session::new();
+        run_all(&mut session, &["fun f() {} fun g() {} var h = f;"]).unwrap();
+        assert_eq!(*session.evaluate("f == f").unwrap(), Value::Bool(true));
+        assert_eq!(*session.evaluate("f == h").unwrap(), Value::Bool(true));
+        assert_eq!(*session.evaluate("f == g").unwrap(), Value::Bool(false));
+        assert_eq!(*session.evaluate("len == len").unwrap(), Value::Bool(true));
+        assert_eq!(*session.evaluate("len != push").unwrap(), Value::Bool(true));
+    }
+
+    #[test]
+    fn lists_and_maps_that_contain_themselves_print_and_compare() {
+        let mut session = Session::new();
+        run_all(
+            &mut session,
+            &["var a = [1]; push(a, a); var b = [1]; push(b, b); var m = {}; m[\"m\"] = m;"],
+        )
+        .unwrap();
+        assert_eq!(session.evaluate("a").unwrap().to_string(), "[1, [...]]");
+        assert_eq!(session.evaluate("m").unwrap().to_string(), "{\"m\": {...}}");
+        assert_eq!(*session.evaluate("a == b").unwrap(), Value::Bool(true));
+    }
+
+    #[test]
+    fn another_thread_can_cancel_a_script_that_never_ends() {
+        let mut session = Session::new();
+        session.set_budget(Budget::default());
+        let handle = session.interpreter().cancel_handle();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.cancel();
+        });
+        let result = run_all(&mut session, &["while (true) {}"]);
+        canceller.join().unwrap();
+        assert!(matches!(result, Err(RloxError::Exhausted(Limit::Cancelled))));
+        // a later budget doesn't undo the cancellation
+        session.set_budget(Budget::default());
+        let result = run_all(&mut session, &["print 1;"]);
+        assert!(matches!(result, Err(RloxError::Exhausted(Limit::Cancelled))));
+    }
+
+    #[test]
+    fn allocations_count_created_values_not_returned_ones() {
+        let mut session = Session::new();
+        run_all(&mut session, &["fun same(x) { return x; } var l = [1]; var s = \"s\";"]).unwrap();
+        session.set_budget(Budget {
+            max_allocations: Some(0),
+            ..Budget::default()
+        });
+        run_all(&mut session, &["for (var i = 0; i < 100; i = i + 1) { same(l); same(s); same(1); }"])
+            .expect("passing values through a function creates none");
+        let result = run_all(&mut session, &["upper(s);"]);
+        assert!(matches!(result, Err(RloxError::Exhausted(Limit::Allocations(0)))));
+        let result = run_all(&mut session, &["match (l) { [first, ...rest] => nil; }"]);
+        assert!(matches!(result, Err(RloxError::Exhausted(Limit::Allocations(0)))));
+    }
+
+    #[test]
+    fn import_needs_file_access() {
+        let mut session = Session::new();
+        let error = run_all(&mut session, &["import \"Cargo.toml\" as cargo;"]).expect_err("no file access");
+        assert!(format!("{:?}", error).contains("without file access"), "{:?}", error);
+    }
+}
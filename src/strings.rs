@@ -0,0 +1,241 @@
+use std::rc::Rc;
+
+use crate::callable::*;
+use crate::environment::*;
+use crate::error::*;
+use crate::interpreter::*;
+use crate::natives::{error, expect_number};
+
+/// String natives. Indices and lengths count characters, not bytes.
+pub fn define_string_natives(globals: &Environment) {
+    let natives = [
+        NativeFunction::new("substring", 3, substring),
+        NativeFunction::new("index_of", 2, index_of),
+        NativeFunction::new("split", 2, split),
+        NativeFunction::new("join", 2, join),
+        NativeFunction::new("trim", 1, trim),
+        NativeFunction::new("upper", 1, upper),
+        NativeFunction::new("lower", 1, lower),
+        NativeFunction::new("replace", 3, replace),
+        NativeFunction::new("starts_with", 2, starts_with),
+        NativeFunction::new("ends_with", 2, ends_with),
+        NativeFunction::new("chars", 1, chars),
+        NativeFunction::new("repeat", 2, repeat),
+        NativeFunction::with_arity("format", Arity { min: 1, max: None }, format),
+        NativeFunction::new("to_number", 1, to_number),
+    ];
+    for native in natives {
+        globals.define(native.name, Rc::new(Value::Func(Rc::new(native))));
+    }
+}
+
+/// The most bytes a string built by `repeat` may hold.
+const MAX_STRING_LEN: usize = 1 << 30;
+
+pub fn expect_string<'a>(name: &str, value: &'a Value) -> Result<&'a str, RloxError> {
+    match value {
+        Value::Str(s) => Ok(s),
+        _ => Err(error(
+            name,
+            format!("{} expects a string, not a {}.", name, value.type_name()),
+        )),
+    }
+}
+
+/// A character position in a string of `len` characters; `len` itself is allowed,
+/// for the end of a range.
+fn char_index(name: &str, index: &Value, len: usize) -> Result<usize, RloxError> {
+    let n = expect_number(name, index)?;
+    if n.fract() != 0.0 || n.is_nan() {
+        return Err(error(name, format!("String index {} is not a whole number.", index)));
+    }
+    if n < 0.0 || n > len as f64 {
+        return Err(error(
+            name,
+            format!("String index {} is out of bounds for a string of length {}.", index, len),
+        ));
+    }
+    Ok(n as usize)
+}
+
+fn substring(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("substring", &args[0])?;
+    let len = s.chars().count();
+    let start = char_index("substring", &args[1], len)?;
+    let end = char_index("substring", &args[2], len)?;
+    if start > end {
+        return Err(error(
+            "substring",
+            format!("Substring start {} is after its end {}.", start, end),
+        ));
+    }
+    interpreter.allocate(Value::Str(s.chars().skip(start).take(end - start).collect()))
+}
+
+/// The character position of the first occurrence, or nil when there is none.
+fn index_of(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("index_of", &args[0])?;
+    let needle = expect_string("index_of", &args[1])?;
+    Ok(Rc::new(match s.find(needle) {
+        Some(byte) => Value::Number(s[..byte].chars().count() as f64),
+        None => Value::Nil,
+    }))
+}
+
+/// Splitting on the empty string gives the characters, like `chars`.
+fn split(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("split", &args[0])?;
+    let separator = expect_string("split", &args[1])?;
+    let parts: Vec<_> = if separator.is_empty() {
+        s.chars().map(|c| Rc::new(Value::Str(c.to_string()))).collect()
+    } else {
+        s.split(separator)
+            .map(|part| Rc::new(Value::Str(part.to_string())))
+            .collect()
+    };
+    interpreter.count_allocations(parts.len())?;
+    interpreter.allocate(Value::new_list(parts))
+}
+
+fn join(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let Value::List(list) = args[0].as_ref() else {
+        return Err(error(
+            "join",
+            format!("join expects a list, not a {}.", args[0].type_name()),
+        ));
+    };
+    let separator = expect_string("join", &args[1])?;
+    let parts: Vec<String> = list.borrow().iter().map(|value| value.to_string()).collect();
+    interpreter.allocate(Value::Str(parts.join(separator)))
+}
+
+fn trim(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    interpreter.allocate(Value::Str(expect_string("trim", &args[0])?.trim().to_string()))
+}
+
+fn upper(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    interpreter.allocate(Value::Str(expect_string("upper", &args[0])?.to_uppercase()))
+}
+
+fn lower(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    interpreter.allocate(Value::Str(expect_string("lower", &args[0])?.to_lowercase()))
+}
+
+fn replace(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("replace", &args[0])?;
+    let from = expect_string("replace", &args[1])?;
+    let to = expect_string("replace", &args[2])?;
+    if from.is_empty() {
+        return Err(error("replace", "Can't replace the empty string.".to_string()));
+    }
+    interpreter.allocate(Value::Str(s.replace(from, to)))
+}
+
+fn starts_with(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("starts_with", &args[0])?;
+    let prefix = expect_string("starts_with", &args[1])?;
+    Ok(Rc::new(Value::Bool(s.starts_with(prefix))))
+}
+
+fn ends_with(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("ends_with", &args[0])?;
+    let suffix = expect_string("ends_with", &args[1])?;
+    Ok(Rc::new(Value::Bool(s.ends_with(suffix))))
+}
+
+fn chars(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("chars", &args[0])?;
+    let chars: Vec<_> = s.chars().map(|c| Rc::new(Value::Str(c.to_string()))).collect();
+    interpreter.count_allocations(chars.len())?;
+    interpreter.allocate(Value::new_list(chars))
+}
+
+fn repeat(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("repeat", &args[0])?;
+    let count = expect_number("repeat", &args[1])?;
+    if count.fract() != 0.0 || count.is_nan() || count < 0.0 {
+        return Err(error(
+            "repeat",
+            format!("Repeat count {} is not a whole number of at least 0.", args[1]),
+        ));
+    }
+    let len = s.len().checked_mul(count as usize).filter(|&len| len <= MAX_STRING_LEN);
+    if len.is_none() {
+        return Err(error(
+            "repeat",
+            format!("Repeating a string {} times makes it too long.", args[1]),
+        ));
+    }
+    interpreter.allocate(Value::Str(s.repeat(count as usize)))
+}
+
+/// `format("{} of {}", a, b)` or `format("{1} of {0}", b, a)`; `{{` and `}}` stand for braces.
+fn format(interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let template = expect_string("format", &args[0])?;
+    let values = &args[1..];
+    let mut output = String::new();
+    let mut next = 0;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let Some(close) = rest.find('}') else {
+                    return Err(error("format", "Unclosed '{' in format string.".to_string()));
+                };
+                let placeholder = &rest[..close];
+                let index = if placeholder.is_empty() {
+                    next += 1;
+                    next - 1
+                } else {
+                    placeholder.parse::<usize>().map_err(|_| {
+                        error(
+                            "format",
+                            format!("Placeholder '{{{}}}' is not a position.", placeholder),
+                        )
+                    })?
+                };
+                let value = values.get(index).ok_or_else(|| {
+                    error(
+                        "format",
+                        format!(
+                            "Placeholder {} is out of range for {} argument(s).",
+                            index,
+                            values.len()
+                        ),
+                    )
+                })?;
+                output.push_str(&value.to_string());
+                chars = rest[close + 1..].chars();
+            }
+            '}' => return Err(error("format", "Unmatched '}' in format string.".to_string())),
+            c => output.push(c),
+        }
+    }
+    interpreter.allocate(Value::Str(output))
+}
+
+/// The number a string spells, ignoring surrounding whitespace, or nil if it spells none.
+fn to_number(_interpreter: &mut Interpreter, args: &[Rc<Value>]) -> Result<Rc<Value>, RloxError> {
+    let s = expect_string("to_number", &args[0])?;
+    Ok(Rc::new(match parse_number(s) {
+        Some(n) => Value::Number(n),
+        None => Value::Nil,
+    }))
+}
+
+/// Reads a number the way a script would write one, ignoring surrounding whitespace.
+pub fn parse_number(s: &str) -> Option<f64> {
+    let s = s.trim();
+    // Rust also reads "inf" and "NaN", which are not number literals anywhere else
+    let numeric = s.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+    s.parse().ok().filter(|_| numeric)
+}